@@ -0,0 +1,279 @@
+//! Recording of parameter changes of an object and their replay.
+//!
+//! Changes are stamped with the number of frames processed since the
+//! recording started. On replay, the wrapper splits the processed block at
+//! the frame each change is due, so changes of a script written by hand or
+//! of a take recorded with another block size land where they belong too.
+//! Objects processing multiple frames at once take a change at the start of
+//! the granule it falls into.
+//!
+//! Recordings are stored in the script format of `automaton-render`, so a
+//! take can be rendered offline.
+
+use std::fmt::Write;
+
+use crate::render::{Instrument, Script};
+use crate::wrapper::Parameter;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Event {
+    frame: u64,
+    parameter: usize,
+    value: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Recording,
+    Playing { looping: bool },
+}
+
+pub struct Recorder {
+    events: Vec<Event>,
+    /// Length of the take, where looped playback starts over.
+    length: u64,
+    state: State,
+    position: u64,
+    cursor: usize,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            length: 0,
+            state: State::Idle,
+            position: 0,
+            cursor: 0,
+        }
+    }
+}
+
+impl Recorder {
+    /// Start a new take from the given values, so it can be replayed from
+    /// the same state.
+    pub fn record(&mut self, initial: &[Option<f32>]) {
+        self.events = initial
+            .iter()
+            .enumerate()
+            .filter_map(|(parameter, value)| {
+                value.map(|value| Event {
+                    frame: 0,
+                    parameter,
+                    value,
+                })
+            })
+            .collect();
+        self.length = 0;
+        self.position = 0;
+        self.state = State::Recording;
+    }
+
+    /// Stop recording or playback.
+    pub fn stop(&mut self) {
+        if self.state == State::Recording {
+            self.length = self.position;
+        }
+        self.state = State::Idle;
+    }
+
+    pub fn play(&mut self, looping: bool) {
+        self.position = 0;
+        self.cursor = 0;
+        self.state = State::Playing { looping };
+    }
+
+    /// Remember a change of a parameter while recording.
+    pub fn capture(&mut self, parameter: usize, value: f32) {
+        if self.state == State::Recording {
+            self.events.push(Event {
+                frame: self.position,
+                parameter,
+                value,
+            });
+        }
+    }
+
+    /// Next change due at or before the given frame of the processed block.
+    pub fn next_due(&mut self, frame: usize) -> Option<(usize, f32)> {
+        if !matches!(self.state, State::Playing { .. }) {
+            return None;
+        }
+        let event = self.events.get(self.cursor)?;
+        if event.frame > self.position + frame as u64 {
+            return None;
+        }
+        self.cursor += 1;
+        Some((event.parameter, event.value))
+    }
+
+    /// Frame of the processed block the next change is due at, if it falls
+    /// within the block.
+    pub fn next_frame(&self) -> Option<usize> {
+        if !matches!(self.state, State::Playing { .. }) {
+            return None;
+        }
+        let event = self.events.get(self.cursor)?;
+        usize::try_from(event.frame.saturating_sub(self.position)).ok()
+    }
+
+    /// Move the clock past a processed block.
+    pub fn advance(&mut self, number_of_frames: usize) {
+        match self.state {
+            State::Idle => return,
+            State::Recording => (),
+            State::Playing { looping } => {
+                if self.position + number_of_frames as u64 >= self.length
+                    && self.cursor == self.events.len()
+                {
+                    if looping && self.length > 0 {
+                        self.position = (self.position + number_of_frames as u64) % self.length;
+                        self.cursor = 0;
+                    } else {
+                        self.state = State::Idle;
+                    }
+                    return;
+                }
+            }
+        }
+        self.position += number_of_frames as u64;
+    }
+
+    pub fn to_script<T>(
+        &self,
+        name: &str,
+        parameters: &[Parameter<T>],
+        sample_rate: f32,
+    ) -> String {
+        let instrument = name.trim_end_matches('~');
+        let seconds = |frame: u64| frame as f64 / f64::from(sample_rate);
+        let mut script = format!("# length={}\n", seconds(self.length));
+        for event in &self.events {
+            let _ = writeln!(
+                script,
+                "t={} {} {} {}",
+                seconds(event.frame),
+                instrument,
+                parameters[event.parameter].name,
+                event.value
+            );
+        }
+        script
+    }
+
+    /// Load messages of the instrument from a script, ignoring messages of
+    /// others. Without a `# length=` comment, the take ends with its last
+    /// message.
+    pub fn from_script(
+        text: &str,
+        name: &str,
+        find_parameter: fn(&str) -> Option<usize>,
+        sample_rate: f32,
+    ) -> Result<Self, String> {
+        let script: Script = text.parse().map_err(|e| format!("{}", e))?;
+        let instrument: Instrument = name.parse()?;
+        let sample_rate = sample_rate as u32;
+
+        let events: Vec<_> = script
+            .events()
+            .iter()
+            .filter(|e| e.instrument == instrument)
+            .map(|e| Event {
+                frame: e.frame(sample_rate) as u64,
+                parameter: find_parameter(&e.parameter).expect("parameters are validated"),
+                value: e.value,
+            })
+            .collect();
+
+        let last = events.last().map_or(0, |e| e.frame);
+        let length = text
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("# length="))
+            .and_then(|length| length.trim().parse::<f64>().ok())
+            .map_or(last, |length| {
+                (length * f64::from(sample_rate)).round() as u64
+            })
+            .max(last);
+
+        Ok(Self {
+            events,
+            length,
+            ..Self::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrapper::{FloatMethod, Kind, PdFloat};
+
+    fn find_parameter(name: &str) -> Option<usize> {
+        ["dry_wet", "tone"].iter().position(|p| *p == name)
+    }
+
+    fn replay(recorder: &mut Recorder, blocks: usize) -> Vec<(usize, usize, f32)> {
+        let mut applied = Vec::new();
+        for block in 0..blocks {
+            while let Some((parameter, value)) = recorder.next_due(0) {
+                applied.push((block, parameter, value));
+            }
+            recorder.advance(64);
+        }
+        applied
+    }
+
+    #[test]
+    fn it_replays_changes_before_the_same_block() {
+        let mut recorder = Recorder::default();
+        recorder.record(&[Some(0.5), None]);
+        recorder.advance(64);
+        recorder.capture(1, 0.2);
+        recorder.advance(64);
+        recorder.advance(64);
+        recorder.capture(0, 1.0);
+        recorder.advance(64);
+        recorder.stop();
+
+        recorder.play(false);
+        let expected = vec![(0, 0, 0.5), (1, 1, 0.2), (3, 0, 1.0)];
+        assert_eq!(replay(&mut recorder, 8), expected);
+
+        recorder.play(true);
+        let looped = replay(&mut recorder, 8);
+        assert_eq!(looped.len(), 6);
+        assert_eq!(looped[3], (4, 0, 0.5));
+        assert_eq!(looped[5], (7, 0, 1.0));
+    }
+
+    #[test]
+    fn it_loads_saved_recording_to_the_frame() {
+        unsafe extern "C" fn noop(_object: *mut std::os::raw::c_void, _value: PdFloat) {}
+        let parameters: Vec<Parameter<()>> = ["dry_wet", "tone"]
+            .into_iter()
+            .map(|name| Parameter {
+                name,
+                kind: Kind::Continuous,
+                range: 0.0..=1.0,
+                method: noop as FloatMethod,
+                set: |_, _| {},
+            })
+            .collect();
+
+        let mut recorder = Recorder::default();
+        recorder.record(&[]);
+        for block in 0..1000 {
+            if block % 7 == 0 {
+                recorder.capture(block % 2, block as f32 / 1000.0);
+            }
+            recorder.advance(64);
+        }
+        recorder.stop();
+
+        let script = recorder.to_script("kaseta~", &parameters, 44100.0);
+        let loaded = Recorder::from_script(&script, "kaseta~", find_parameter, 44100.0).unwrap();
+        assert_eq!(loaded.events, recorder.events);
+        assert_eq!(loaded.length, recorder.length);
+    }
+}
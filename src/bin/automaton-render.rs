@@ -0,0 +1,180 @@
+//! Render instruments of the library into a WAV file without Pure Data.
+//!
+//! ```sh
+//! automaton-render script.txt output.wav --length 10
+//! automaton-render script.txt output.wav --input guitar.wav --sample-rate 44100
+//! ```
+//!
+//! See `automaton::render` for the format of the script.
+
+use std::path::{Path, PathBuf};
+use std::process;
+
+use automaton::render::{self, Instrument, Script};
+
+const USAGE: &str = "usage: automaton-render <script> <output.wav> \
+                     [--input <input.wav>] [--sample-rate <hz>] [--length <seconds>]";
+
+struct Arguments {
+    script: PathBuf,
+    output: PathBuf,
+    input: Option<PathBuf>,
+    sample_rate: u32,
+    length: Option<f32>,
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("automaton-render: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let arguments = parse_arguments(std::env::args().skip(1))?;
+
+    let script = std::fs::read_to_string(&arguments.script)
+        .map_err(|e| format!("failed to read {}: {}", arguments.script.display(), e))?;
+    let script: Script = script
+        .parse()
+        .map_err(|e| format!("{}: {}", arguments.script.display(), e))?;
+    if !script.uses(Instrument::Achordion) && !script.uses(Instrument::Kaseta) {
+        return Err("the script does not send any message".to_string());
+    }
+
+    let input = match &arguments.input {
+        Some(path) => {
+            if !script.uses(Instrument::Kaseta) {
+                return Err("input is only processed by kaseta, which is not in the script".into());
+            }
+            Some(read_wav(path, arguments.sample_rate)?)
+        }
+        None => None,
+    };
+
+    let number_of_frames = match (arguments.length, &input) {
+        (Some(length), _) => (length * arguments.sample_rate as f32) as usize,
+        (None, Some(input)) => input[0].len(),
+        (None, None) => return Err("--length is required when there is no input".into()),
+    };
+
+    let output = render::render(
+        &script,
+        input.as_ref().map(|input| [&input[0][..], &input[1][..]]),
+        arguments.sample_rate,
+        number_of_frames,
+    )?;
+
+    write_wav(&arguments.output, &output, arguments.sample_rate)
+}
+
+fn parse_arguments(mut arguments: impl Iterator<Item = String>) -> Result<Arguments, String> {
+    let mut positional = Vec::new();
+    let mut input = None;
+    let mut sample_rate = 48000;
+    let mut length = None;
+
+    while let Some(argument) = arguments.next() {
+        let mut value = |name: &str| {
+            arguments
+                .next()
+                .ok_or_else(|| format!("{} expects a value\n{}", name, USAGE))
+        };
+        match argument.as_str() {
+            "--input" => input = Some(PathBuf::from(value("--input")?)),
+            "--sample-rate" => {
+                sample_rate = value("--sample-rate")?
+                    .parse()
+                    .map_err(|_| "--sample-rate expects a whole number".to_string())?;
+            }
+            "--length" => {
+                length = Some(
+                    value("--length")?
+                        .parse()
+                        .map_err(|_| "--length expects seconds".to_string())?,
+                );
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if argument.starts_with("--") => {
+                return Err(format!("unknown option {}\n{}", argument, USAGE));
+            }
+            _ => positional.push(PathBuf::from(argument)),
+        }
+    }
+
+    if positional.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+    let output = positional.pop().unwrap();
+    let script = positional.pop().unwrap();
+
+    Ok(Arguments {
+        script,
+        output,
+        input,
+        sample_rate,
+        length,
+    })
+}
+
+/// Read mono or stereo file, returning it as two channels.
+fn read_wav(path: &Path, sample_rate: u32) -> Result<[Vec<f32>; 2], String> {
+    let error = |e: hound::Error| format!("failed to read {}: {}", path.display(), e);
+
+    let mut reader = hound::WavReader::open(path).map_err(error)?;
+    let spec = reader.spec();
+    if spec.sample_rate != sample_rate {
+        return Err(format!(
+            "{} is sampled at {} Hz, pass --sample-rate {} or resample it",
+            path.display(),
+            spec.sample_rate,
+            spec.sample_rate
+        ));
+    }
+    if spec.channels > 2 {
+        return Err(format!("{} has more than two channels", path.display()));
+    }
+
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+        hound::SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()
+        }
+    }
+    .map_err(error)?;
+
+    let channels = spec.channels as usize;
+    let left: Vec<f32> = samples.iter().step_by(channels).copied().collect();
+    let right = if channels == 2 {
+        samples.iter().skip(1).step_by(2).copied().collect()
+    } else {
+        left.clone()
+    };
+
+    Ok([left, right])
+}
+
+fn write_wav(path: &Path, channels: &[Vec<f32>], sample_rate: u32) -> Result<(), String> {
+    let error = |e: hound::Error| format!("failed to write {}: {}", path.display(), e);
+
+    let spec = hound::WavSpec {
+        channels: channels.len() as u16,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(error)?;
+    for i in 0..channels[0].len() {
+        for channel in channels {
+            writer.write_sample(channel[i]).map_err(error)?;
+        }
+    }
+    writer.finalize().map_err(error)
+}
@@ -0,0 +1,99 @@
+//! Selection of discrete parameters by name, e.g. `bank soft`, and reporting
+//! of the names of their active values.
+//!
+//! Values may change while a block is being processed, by control voltage,
+//! OSC or a replayed recording. Reports are therefore sent from a clock, once
+//! the DSP tick is over.
+
+use std::ops::RangeInclusive;
+
+use crate::clock::Clock;
+use crate::wrapper::{Atom, Outlet};
+
+/// Names of values of a discrete parameter. Its range is split into equal
+/// parts, one for each name, in the order they are listed.
+pub struct Choice {
+    /// Selector of the message picking a value by name.
+    pub selector: &'static str,
+    pub parameter: &'static str,
+    pub names: &'static [&'static str],
+}
+
+impl Choice {
+    /// Middle of the part of the range belonging to the name.
+    pub fn value(&self, name: &str, range: &RangeInclusive<f32>) -> Option<f32> {
+        let index = self.names.iter().position(|n| *n == name)?;
+        let width = (range.end() - range.start()) / self.names.len() as f32;
+        Some(range.start() + width * (index as f32 + 0.5))
+    }
+
+    /// Index of the name whose part of the range holds the value.
+    pub fn index(&self, value: f32, range: &RangeInclusive<f32>) -> usize {
+        let position = (value - range.start()) / (range.end() - range.start());
+        let last = self.names.len() - 1;
+        ((position * self.names.len() as f32).max(0.0) as usize).min(last)
+    }
+}
+
+/// Outlet announcing names of active values as they change.
+pub struct Reporter {
+    outlet: Outlet,
+    clock: Box<dyn Clock>,
+    reported: Vec<Option<usize>>,
+}
+
+impl Reporter {
+    /// The clock should call `report` once it is due.
+    pub fn new(outlet: Outlet, clock: Box<dyn Clock>, number_of_choices: usize) -> Self {
+        Self {
+            outlet,
+            clock,
+            reported: vec![None; number_of_choices],
+        }
+    }
+
+    /// Schedule a report if any of the active names differs from the last
+    /// reported one. It does not allocate, so it can be called on every
+    /// processed block.
+    pub fn update(&self, active: impl Iterator<Item = Option<usize>>) {
+        if self.reported.iter().copied().ne(active) {
+            self.clock.delay(0.0);
+        }
+    }
+
+    /// Send `<selector> <name>` for each choice whose name changed.
+    pub fn report(&mut self, choices: &[Choice], active: impl Iterator<Item = Option<usize>>) {
+        for ((choice, reported), active) in choices.iter().zip(&mut self.reported).zip(active) {
+            if let (Some(index), true) = (active, active != *reported) {
+                self.outlet
+                    .anything(choice.selector, &[Atom::Symbol(choice.names[index])]);
+            }
+            *reported = active;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruments::achordion::BANK;
+
+    #[test]
+    fn it_maps_names_to_middle_of_their_part_of_range() {
+        assert_eq!(BANK.value("perfect", &(0.0..=1.0)), Some(0.125));
+        assert_eq!(BANK.value("soft", &(0.0..=1.0)), Some(0.625));
+        assert_eq!(BANK.value("soft", &(-5.0..=5.0)), Some(1.25));
+        assert_eq!(BANK.value("fancy", &(0.0..=1.0)), None);
+
+        for (value, index) in [
+            (0.0, 0),
+            (0.2499, 0),
+            (0.25, 1),
+            (0.74, 2),
+            (1.0, 3),
+            (7.0, 3),
+        ] {
+            assert_eq!(BANK.index(value, &(0.0..=1.0)), index);
+        }
+        assert_eq!(BANK.index(-1.0, &(0.0..=1.0)), 0);
+    }
+}
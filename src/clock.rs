@@ -0,0 +1,45 @@
+//! Clocks calling back into their owner once due, outside of the DSP tick.
+
+use std::os::raw::c_void;
+
+use crate::wrapper::BangMethod;
+
+pub trait Clock {
+    /// Schedule the tick after the given number of milliseconds of logical
+    /// time, replacing any scheduled before.
+    fn delay(&self, milliseconds: f64);
+
+    /// Cancel the scheduled tick, if any.
+    fn unset(&self);
+}
+
+/// Clock of Pure Data, calling a method of its owner.
+///
+/// Holders of clocks keep them as `dyn Clock`. Functions of Pure Data are
+/// then only reached through clocks made within it, and holders can be
+/// built and dropped without it, e.g. by unit tests or the offline renderer.
+pub struct PdClock(*mut pd_sys::t_clock);
+
+impl PdClock {
+    /// The owner is the Pure Data object passed to the method.
+    pub unsafe fn new(owner: *mut c_void, tick: BangMethod) -> Self {
+        let tick = std::mem::transmute::<BangMethod, unsafe extern "C" fn()>(tick);
+        Self(pd_sys::clock_new(owner, Some(tick)))
+    }
+}
+
+impl Clock for PdClock {
+    fn delay(&self, milliseconds: f64) {
+        unsafe { pd_sys::clock_delay(self.0, milliseconds) };
+    }
+
+    fn unset(&self) {
+        unsafe { pd_sys::clock_unset(self.0) };
+    }
+}
+
+impl Drop for PdClock {
+    fn drop(&mut self) {
+        unsafe { pd_sys::clock_free(self.0) };
+    }
+}
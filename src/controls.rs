@@ -0,0 +1,156 @@
+//! State kept by the wrapper for objects with parameters, and the splitting
+//! of processed blocks that lets parameters change within them.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::automation::Recorder;
+use crate::choice::Reporter;
+use crate::midi::{Bindings, MidiMap};
+use crate::osc::Inbox;
+use crate::preset::Presets;
+use crate::wrapper::Parameter;
+
+/// Frames between two steps of a glide.
+const GLIDE_STEP: usize = 16;
+
+pub struct Controls {
+    pub presets: Presets,
+    pub midi: MidiMap,
+    /// Made by the wrapper once the object lives in Pure Data.
+    pub bindings: Option<Bindings>,
+    /// Shared with the OSC server.
+    pub inbox: Arc<Inbox>,
+    pub recorder: Recorder,
+    /// Made by the wrapper for classes with choices.
+    pub choices: Option<Reporter>,
+    pub sample_rate: f32,
+    /// Directory of the patch, relative paths of recordings are resolved
+    /// from it.
+    pub directory: PathBuf,
+    granularity: usize,
+}
+
+impl Controls {
+    /// Parts of blocks are always a multiple of the granularity long.
+    pub fn new(number_of_parameters: usize, granularity: usize, sample_rate: f32) -> Self {
+        Self {
+            presets: Presets::new(number_of_parameters),
+            midi: MidiMap::default(),
+            bindings: None,
+            inbox: Arc::default(),
+            recorder: Recorder::default(),
+            choices: None,
+            sample_rate,
+            directory: PathBuf::new(),
+            granularity: granularity.max(1),
+        }
+    }
+
+    /// Next replayed change to be applied before the part of the processed
+    /// block starting at the given frame. Changes due anywhere within the
+    /// first granule of the part are included.
+    pub fn next_replayed(&mut self, start: usize) -> Option<(usize, f32)> {
+        self.recorder.next_due(start + self.granularity - 1)
+    }
+
+    /// Apply changes due at the start of a part of the processed block and
+    /// return where the part ends. Gliding parameters move once per part,
+    /// so a glide is split into parts of a few frames. A part also ends
+    /// where the next replayed change is due, rounded down to the
+    /// granularity.
+    pub fn step<T>(
+        &mut self,
+        state: &mut T,
+        parameters: &[Parameter<T>],
+        start: usize,
+        number_of_frames: usize,
+        block_duration: f32,
+    ) -> usize {
+        let mut end = number_of_frames;
+        if self.presets.is_gliding() {
+            end = end.min(start + GLIDE_STEP.next_multiple_of(self.granularity));
+        }
+        if let Some(frame) = self.recorder.next_frame() {
+            let granule = frame / self.granularity * self.granularity;
+            end = end.min(granule.max(start + self.granularity));
+        }
+
+        let elapsed = block_duration * (end - start) as f32 / number_of_frames as f32;
+        self.presets.advance(state, parameters, elapsed);
+
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrapper::{FloatMethod, Kind, PdFloat};
+
+    unsafe extern "C" fn noop(_object: *mut std::os::raw::c_void, _value: PdFloat) {}
+
+    fn parameters() -> Vec<Parameter<f32>> {
+        vec![Parameter {
+            name: "knob",
+            kind: Kind::Continuous,
+            range: 0.0..=1.0,
+            method: noop as FloatMethod,
+            set: |s, v| *s = v,
+        }]
+    }
+
+    #[test]
+    fn it_glides_in_steps_within_a_block() {
+        const BLOCK: usize = 64;
+        let parameters = parameters();
+        let mut controls = Controls::new(parameters.len(), 1, 48000.0);
+        let mut state = 0.0;
+
+        controls.presets.set(0, 0.0);
+        controls.presets.store(1, &parameters);
+        controls.presets.set(0, 1.0);
+        controls.presets.store(2, &parameters);
+        controls
+            .presets
+            .morph((1, 2), 0.0, &mut state, &parameters)
+            .unwrap();
+
+        let mut values = Vec::new();
+        let mut start = 0;
+        while start < BLOCK {
+            start = controls.step(&mut state, &parameters, start, BLOCK, 0.01);
+            values.push(state);
+        }
+
+        assert_eq!(values.len(), BLOCK / GLIDE_STEP);
+        assert!(values.windows(2).all(|w| w[1] < w[0]));
+        assert!(values[0] < 1.0);
+    }
+
+    #[test]
+    fn it_ends_parts_where_replayed_changes_are_due() {
+        let parameters = parameters();
+        let script = "t=0 kaseta dry_wet 0.1\nt=0.040 kaseta tone 0.2\n";
+        let find_parameter = |name: &str| ["dry_wet", "tone"].iter().position(|p| *p == name);
+        let mut controls = Controls::new(parameters.len(), 32, 1000.0);
+        controls.recorder =
+            Recorder::from_script(script, "kaseta~", find_parameter, 1000.0).unwrap();
+        controls.recorder.play(false);
+        let mut state = 0.0;
+
+        assert_eq!(controls.next_replayed(0), Some((0, 0.1)));
+        assert_eq!(controls.next_replayed(0), None);
+        assert_eq!(controls.step(&mut state, &parameters, 0, 64, 0.064), 32);
+        assert_eq!(controls.next_replayed(32), Some((1, 0.2)));
+        assert_eq!(controls.step(&mut state, &parameters, 32, 64, 0.064), 64);
+    }
+
+    #[test]
+    fn it_keeps_blocks_whole_while_nothing_glides() {
+        let parameters = parameters();
+        let mut controls = Controls::new(parameters.len(), 32, 48000.0);
+        let mut state = 0.0;
+        assert_eq!(controls.step(&mut state, &parameters, 0, 64, 0.01), 64);
+    }
+}
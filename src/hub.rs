@@ -0,0 +1,232 @@
+//! The `[automaton]` object, reporting on the state of the library.
+//!
+//! Every query is answered through the outlet and printed to the console.
+
+use std::net::IpAddr;
+
+use crate::instance;
+use crate::log::Logger;
+use crate::osc;
+use crate::pool::{self, MEGABYTE};
+use crate::wrapper::{self, Atom, Class, Context, Outlet, PdClass};
+
+pub(crate) struct Automaton {
+    outlet: Outlet,
+    logger: Logger,
+    osc: Option<osc::Server>,
+}
+
+impl PdClass for Automaton {
+    const NAME: &'static str = "automaton";
+
+    /// The optional creation argument sets the size of the tape memory pool
+    /// in megabytes.
+    fn new(context: &mut Context) -> Result<Self, String> {
+        let mut automaton = Self {
+            outlet: context.new_outlet(),
+            logger: context.logger(),
+            osc: None,
+        };
+        if let Some(megabytes) = context.arguments().first().and_then(Atom::float) {
+            automaton.resize_pool(megabytes);
+        }
+        Ok(automaton)
+    }
+
+    fn register(class: &mut Class<Self>) {
+        class.add_method("version", bang_method!(Automaton::version));
+        class.add_method("modules", bang_method!(Automaton::modules));
+        class.add_method("memory", bang_method!(Automaton::memory));
+        class.add_method("sr", bang_method!(Automaton::sample_rates));
+        class.add_gimme_method("pool", gimme_method!(Automaton::pool));
+        class.add_method("cpu", bang_method!(Automaton::cpu));
+        class.add_gimme_method("osc", gimme_method!(Automaton::osc));
+    }
+}
+
+impl Automaton {
+    fn version(&mut self) {
+        let version = env!("CARGO_PKG_VERSION");
+        self.logger
+            .info(&format!("[automaton] version {}", version));
+        self.outlet.anything("version", &[Atom::Symbol(version)]);
+    }
+
+    /// Report every registered module with the number of its objects.
+    fn modules(&mut self) {
+        let objects = wrapper::objects();
+        for name in wrapper::classes() {
+            if name == Self::NAME {
+                continue;
+            }
+            let count = objects.iter().filter(|o| o.name == name).count();
+            self.logger
+                .info(&format!("[automaton] module {}: {} objects", name, count));
+            self.outlet
+                .anything("modules", &[Atom::Symbol(name), Atom::Float(count as f32)]);
+        }
+    }
+
+    /// Report used and total tape memory in megabytes.
+    fn memory(&mut self) {
+        let usage = pool::usage();
+        let (used, capacity) = (usage.total as f32 / MEGABYTE, usage.size as f32 / MEGABYTE);
+        self.logger.info(&format!(
+            "[automaton] tape memory: {:.1} of {:.1} MB used",
+            used, capacity
+        ));
+        self.outlet
+            .anything("memory", &[Atom::Float(used), Atom::Float(capacity)]);
+    }
+
+    /// With an argument set the size of the tape memory pool in megabytes,
+    /// without one report memory used by this Pure Data instance, by all of
+    /// them and the size of the pool.
+    fn pool(&mut self, arguments: &[Atom]) {
+        match arguments.first().map(Atom::float) {
+            Some(Some(megabytes)) => self.resize_pool(megabytes),
+            Some(None) => self
+                .logger
+                .error("[automaton] pool expects size in megabytes"),
+            None => {
+                let usage = pool::usage();
+                let instance = usage.instance as f32 / MEGABYTE;
+                let total = usage.total as f32 / MEGABYTE;
+                let size = usage.size as f32 / MEGABYTE;
+                self.logger.info(&format!(
+                    "[automaton] pool: {:.1} MB used by this instance, {:.1} of {:.1} MB by all",
+                    instance, total, size
+                ));
+                self.outlet.anything(
+                    "pool",
+                    &[Atom::Float(instance), Atom::Float(total), Atom::Float(size)],
+                );
+            }
+        }
+    }
+
+    fn resize_pool(&mut self, megabytes: f32) {
+        if megabytes <= 0.0 {
+            self.logger.error("[automaton] pool size must be positive");
+            return;
+        }
+        match pool::resize((megabytes * MEGABYTE) as usize) {
+            Ok(()) => self
+                .logger
+                .info(&format!("[automaton] pool set to {} MB", megabytes)),
+            Err(error) => self.logger.warn(&format!("[automaton] {}", error)),
+        }
+    }
+
+    /// Report average and peak DSP load of each instrument in percent of
+    /// the time available for processing of a block.
+    fn cpu(&mut self) {
+        for object in wrapper::objects() {
+            if object.name == Self::NAME {
+                continue;
+            }
+            let (average, peak) = (object.meter.average(), object.meter.peak());
+            self.logger.info(&format!(
+                "[automaton] {}: {:.2}% average, {:.2}% peak",
+                object.name, average, peak
+            ));
+            self.outlet.anything(
+                "cpu",
+                &[
+                    Atom::Symbol(object.name),
+                    Atom::Float(average),
+                    Atom::Float(peak),
+                ],
+            );
+        }
+    }
+
+    /// Report the sample rate each instrument was initialized with.
+    fn sample_rates(&mut self) {
+        for object in wrapper::objects() {
+            if object.name == Self::NAME {
+                continue;
+            }
+            self.logger.info(&format!(
+                "[automaton] {}: {} Hz",
+                object.name, object.sample_rate
+            ));
+            self.outlet.anything(
+                "sr",
+                &[Atom::Symbol(object.name), Atom::Float(object.sample_rate)],
+            );
+        }
+    }
+
+    /// `osc <port> [address]` starts listening for OSC messages controlling
+    /// parameters of instruments, port 0 picks any free one. Only the local
+    /// machine is listened to, unless an address such as 0.0.0.0 is given.
+    /// `osc off` stops it, `osc` alone reports the port with numbers of
+    /// received and dropped messages.
+    fn osc(&mut self, arguments: &[Atom]) {
+        match arguments.first() {
+            Some(Atom::Float(port)) => {
+                if *port < 0.0 || *port > f32::from(u16::MAX) || port.fract() != 0.0 {
+                    self.logger.error("[automaton] osc expects a port number");
+                    return;
+                }
+                let address = match arguments.get(1) {
+                    None => Some(osc::DEFAULT_ADDRESS),
+                    Some(Atom::Symbol(address)) => address.parse::<IpAddr>().ok(),
+                    Some(Atom::Float(_)) => None,
+                };
+                let Some(address) = address else {
+                    self.logger
+                        .error("[automaton] osc expects an IP address to listen on");
+                    return;
+                };
+                // Release the previous port first, in case it is the same.
+                self.osc = None;
+                match osc::Server::start(address, *port as u16, instance::current()) {
+                    Ok(server) => {
+                        let port = server.port();
+                        self.logger.info(&format!(
+                            "[automaton] listening for OSC on {}:{}",
+                            address, port
+                        ));
+                        self.outlet.anything("osc", &[Atom::Float(f32::from(port))]);
+                        self.osc = Some(server);
+                    }
+                    Err(error) => self.logger.error(&format!(
+                        "[automaton] cannot listen on port {}: {}",
+                        port, error
+                    )),
+                }
+            }
+            Some(Atom::Symbol("off")) => {
+                if self.osc.take().is_some() {
+                    self.logger.info("[automaton] stopped listening for OSC");
+                }
+            }
+            Some(Atom::Symbol(_)) => self
+                .logger
+                .error("[automaton] osc expects a port number or off"),
+            None => match &self.osc {
+                Some(server) => {
+                    let statistics = server.statistics();
+                    let (received, dropped) = (statistics.received(), statistics.dropped());
+                    self.logger.info(&format!(
+                        "[automaton] osc: port {}, {} messages received, {} dropped",
+                        server.port(),
+                        received,
+                        dropped
+                    ));
+                    self.outlet.anything(
+                        "osc",
+                        &[
+                            Atom::Float(f32::from(server.port())),
+                            Atom::Float(received as f32),
+                            Atom::Float(dropped as f32),
+                        ],
+                    );
+                }
+                None => self.logger.info("[automaton] osc: not listening"),
+            },
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use std::os::raw::c_void;
+
+/// Identifier of the Pure Data instance currently calling into the library.
+///
+/// Pure Data built with `PDINSTANCE`, as libpd usually is, exports the active
+/// instance through `pd_this`. Builds without it run a single instance only.
+pub fn current() -> usize {
+    // The variable may be thread-local, so its address cannot be cached.
+    let pd_this = unsafe { find_pd_this() };
+    if pd_this.is_null() {
+        0
+    } else {
+        unsafe { *(pd_this as *const *mut c_void) as usize }
+    }
+}
+
+#[cfg(unix)]
+unsafe fn find_pd_this() -> *mut c_void {
+    libc::dlsym(libc::RTLD_DEFAULT, crate::cstr::cstr("pd_this").as_ptr())
+}
+
+#[cfg(not(unix))]
+unsafe fn find_pd_this() -> *mut c_void {
+    std::ptr::null_mut()
+}
@@ -0,0 +1,153 @@
+//! Stepping through tones of the current chord, one on every trigger.
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::choice::Choice;
+
+/// Signal rising above this value triggers the next step.
+const THRESHOLD: f32 = 0.5;
+
+/// Direction of the arpeggio, selected by `arp` or by name.
+pub const DIRECTION: Choice = Choice {
+    selector: "arp",
+    parameter: "arp",
+    names: &["off", "up", "down", "updown", "random"],
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Off,
+    Up,
+    Down,
+    UpDown,
+    Random,
+}
+
+pub struct Arpeggiator {
+    direction: Direction,
+    octaves: usize,
+    /// Steps taken since the arpeggio started.
+    step: usize,
+    high: bool,
+    random: StdRng,
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self {
+            direction: Direction::Off,
+            octaves: 1,
+            step: 0,
+            high: false,
+            random: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl Arpeggiator {
+    pub fn set_direction(&mut self, value: f32) {
+        let direction = match DIRECTION.index(value, &(0.0..=1.0)) {
+            0 => Direction::Off,
+            1 => Direction::Up,
+            2 => Direction::Down,
+            3 => Direction::UpDown,
+            _ => Direction::Random,
+        };
+        if direction != self.direction {
+            self.direction = direction;
+            self.step = 0;
+        }
+    }
+
+    /// Makes the random direction reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.random = StdRng::seed_from_u64(seed);
+    }
+
+    /// Number of octaves the arpeggio spans, 1 to 4.
+    pub fn set_octaves(&mut self, value: f32) {
+        self.octaves = value.round().clamp(1.0, 4.0) as usize;
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.direction != Direction::Off
+    }
+
+    /// Watch the trigger signal, returning true on its rising edge.
+    pub fn detect(&mut self, sample: f32) -> bool {
+        let was_high = self.high;
+        self.high = sample > THRESHOLD;
+        self.high && !was_high
+    }
+
+    /// Index of the next tone out of the chord with the given number of
+    /// tones, counting through all the octaves the arpeggio spans.
+    pub fn advance(&mut self, number_of_tones: usize) -> usize {
+        let length = number_of_tones * self.octaves;
+        let step = self.step;
+        self.step = self.step.wrapping_add(1);
+        match self.direction {
+            Direction::Off | Direction::Up => step % length,
+            Direction::Down => length - 1 - step % length,
+            // The highest and lowest tones are not repeated on the turn.
+            Direction::UpDown if length > 1 => {
+                let cycle = 2 * (length - 1);
+                let position = step % cycle;
+                if position < length {
+                    position
+                } else {
+                    cycle - position
+                }
+            }
+            Direction::UpDown => 0,
+            Direction::Random => self.random.gen_range(0..length),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(direction: &str, octaves: f32, number_of_tones: usize) -> Vec<usize> {
+        let mut arpeggiator = Arpeggiator::default();
+        arpeggiator.set_direction(DIRECTION.value(direction, &(0.0..=1.0)).unwrap());
+        arpeggiator.set_octaves(octaves);
+        (0..8)
+            .map(|_| arpeggiator.advance(number_of_tones))
+            .collect()
+    }
+
+    #[test]
+    fn it_steps_through_tones_in_all_directions() {
+        assert_eq!(sequence("up", 1.0, 3), vec![0, 1, 2, 0, 1, 2, 0, 1]);
+        assert_eq!(sequence("down", 1.0, 3), vec![2, 1, 0, 2, 1, 0, 2, 1]);
+        assert_eq!(sequence("updown", 1.0, 3), vec![0, 1, 2, 1, 0, 1, 2, 1]);
+        assert_eq!(sequence("up", 2.0, 3), vec![0, 1, 2, 3, 4, 5, 0, 1]);
+        assert_eq!(sequence("updown", 1.0, 1), vec![0; 8]);
+        assert!(sequence("random", 2.0, 3).iter().all(|i| *i < 6));
+    }
+
+    #[test]
+    fn it_repeats_random_sequence_of_the_same_seed() {
+        let sequence = |seed| {
+            let mut arpeggiator = Arpeggiator::default();
+            arpeggiator.set_direction(DIRECTION.value("random", &(0.0..=1.0)).unwrap());
+            arpeggiator.set_seed(seed);
+            (0..16).map(|_| arpeggiator.advance(8)).collect::<Vec<_>>()
+        };
+        assert_eq!(sequence(42), sequence(42));
+        assert_ne!(sequence(42), sequence(43));
+    }
+
+    #[test]
+    fn it_triggers_on_rising_edge() {
+        let mut arpeggiator = Arpeggiator::default();
+        let triggers: Vec<_> = [0.0, 1.0, 1.0, 0.0, 0.8, 0.2, 0.9]
+            .iter()
+            .map(|x| arpeggiator.detect(*x))
+            .collect();
+        assert_eq!(triggers, vec![false, true, false, false, true, false, true]);
+    }
+}
@@ -1,17 +1,17 @@
 macro_rules! factors_ref {
-    ( $factors:ident ) => {
+    ( $factors:expr ) => {
         [
-            &$factors.as_ref().unwrap().factor1,
-            &$factors.as_ref().unwrap().factor2,
-            &$factors.as_ref().unwrap().factor4,
-            &$factors.as_ref().unwrap().factor8,
-            &$factors.as_ref().unwrap().factor16,
-            &$factors.as_ref().unwrap().factor32,
-            &$factors.as_ref().unwrap().factor64,
-            &$factors.as_ref().unwrap().factor128,
-            &$factors.as_ref().unwrap().factor256,
-            &$factors.as_ref().unwrap().factor512,
-            &$factors.as_ref().unwrap().factor1024,
+            &$factors.factor1,
+            &$factors.factor2,
+            &$factors.factor4,
+            &$factors.factor8,
+            &$factors.factor16,
+            &$factors.factor32,
+            &$factors.factor64,
+            &$factors.factor128,
+            &$factors.factor256,
+            &$factors.factor512,
+            &$factors.factor1024,
         ]
     };
 }
@@ -21,24 +21,47 @@ mod perfect;
 mod sins;
 mod soft;
 
+use std::collections::HashMap;
+use std::sync::Mutex;
+
 use achordion_lib::wavetable::Wavetable;
 
-type FactorsRef = [&'static [f32]; 11];
+/// Versions of a waveform with fewer and fewer harmonics. Each of them can be
+/// played twice as high as the previous one without aliasing.
+pub type FactorsRef = [&'static [f32]; 11];
+
+type Banks = [&'static [Wavetable<'static>]; 4];
 
-pub static mut WAVETABLE_BANKS: Option<[&'static [Wavetable<'static>]; 4]> = None;
+lazy_static! {
+    static ref WAVETABLE_BANKS: Mutex<HashMap<u32, &'static Banks>> = Mutex::new(HashMap::new());
+}
 
 pub fn setup() {
-    perfect::setup();
-    harsh::setup();
-    soft::setup();
-    sins::setup();
-
-    unsafe {
-        WAVETABLE_BANKS = Some([
-            &perfect::BANK.as_ref().unwrap()[..],
-            &harsh::BANK.as_ref().unwrap()[..],
-            &soft::BANK.as_ref().unwrap()[..],
-            &sins::BANK.as_ref().unwrap()[..],
-        ]);
+    wavetable_banks(crate::wrapper::sample_rate() as u32);
+}
+
+/// Wavetables are bound to a sample rate. They are built once for each rate
+/// in use and then shared by all objects and Pure Data instances running it.
+pub fn wavetable_banks(sample_rate: u32) -> &'static Banks {
+    let mut banks = WAVETABLE_BANKS.lock().unwrap();
+    banks.entry(sample_rate).or_insert_with(|| {
+        Box::leak(Box::new([
+            &Box::leak(Box::new(perfect::bank(sample_rate)))[..],
+            &Box::leak(Box::new(harsh::bank(sample_rate)))[..],
+            &Box::leak(Box::new(soft::bank(sample_rate)))[..],
+            &Box::leak(Box::new(sins::bank(sample_rate)))[..],
+        ]))
+    })
+}
+
+/// Band-limited versions of waveforms of the bank, in the order the
+/// wavetable parameter morphs through them. Unlike wavetables, they are not
+/// bound to a sample rate.
+pub fn factors(bank: usize) -> &'static [FactorsRef] {
+    match bank {
+        0 => perfect::factors(),
+        1 => harsh::factors(),
+        2 => soft::factors(),
+        _ => sins::factors(),
     }
 }
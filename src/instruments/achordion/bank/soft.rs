@@ -4,54 +4,37 @@ use achordion_lib::wavetable::Wavetable;
 
 use super::FactorsRef;
 
-static mut FACTORS_0: Option<Factors> = None;
-static mut FACTORS_0_REF: Option<FactorsRef> = None;
-
-static mut FACTORS_1: Option<Factors> = None;
-static mut FACTORS_1_REF: Option<FactorsRef> = None;
-
-static mut FACTORS_2: Option<Factors> = None;
-static mut FACTORS_2_REF: Option<FactorsRef> = None;
-
-static mut FACTORS_3: Option<Factors> = None;
-static mut FACTORS_3_REF: Option<FactorsRef> = None;
-
-static mut FACTORS_4: Option<Factors> = None;
-static mut FACTORS_4_REF: Option<FactorsRef> = None;
-
-static mut FACTORS_5: Option<Factors> = None;
-static mut FACTORS_5_REF: Option<FactorsRef> = None;
-
-pub static mut BANK: Option<[Wavetable<'static>; 6]> = None;
-
-pub fn setup() {
-    unsafe {
-        FACTORS_0 = Some(Factors::from_raw(&waveform::soft::SOFT_0));
-        FACTORS_0_REF = Some(factors_ref!(FACTORS_0));
-
-        FACTORS_1 = Some(Factors::from_raw(&waveform::soft::SOFT_1));
-        FACTORS_1_REF = Some(factors_ref!(FACTORS_1));
-
-        FACTORS_2 = Some(Factors::from_raw(&waveform::soft::SOFT_2));
-        FACTORS_2_REF = Some(factors_ref!(FACTORS_2));
-
-        FACTORS_3 = Some(Factors::from_raw(&waveform::soft::SOFT_3));
-        FACTORS_3_REF = Some(factors_ref!(FACTORS_3));
-
-        FACTORS_4 = Some(Factors::from_raw(&waveform::soft::SOFT_4));
-        FACTORS_4_REF = Some(factors_ref!(FACTORS_4));
+lazy_static! {
+    static ref FACTORS: [Box<Factors>; 6] = [
+        Box::new(Factors::from_raw(&waveform::soft::SOFT_0)),
+        Box::new(Factors::from_raw(&waveform::soft::SOFT_1)),
+        Box::new(Factors::from_raw(&waveform::soft::SOFT_2)),
+        Box::new(Factors::from_raw(&waveform::soft::SOFT_3)),
+        Box::new(Factors::from_raw(&waveform::soft::SOFT_4)),
+        Box::new(Factors::from_raw(&waveform::soft::SOFT_5)),
+    ];
+    static ref FACTORS_REF: [FactorsRef; 6] = [
+        factors_ref!(FACTORS[0]),
+        factors_ref!(FACTORS[1]),
+        factors_ref!(FACTORS[2]),
+        factors_ref!(FACTORS[3]),
+        factors_ref!(FACTORS[4]),
+        factors_ref!(FACTORS[5]),
+    ];
+}
 
-        FACTORS_5 = Some(Factors::from_raw(&waveform::soft::SOFT_5));
-        FACTORS_5_REF = Some(factors_ref!(FACTORS_5));
+pub fn bank(sample_rate: u32) -> [Wavetable<'static>; 6] {
+    [
+        Wavetable::new(&FACTORS_REF[0], sample_rate),
+        Wavetable::new(&FACTORS_REF[1], sample_rate),
+        Wavetable::new(&FACTORS_REF[2], sample_rate),
+        Wavetable::new(&FACTORS_REF[3], sample_rate),
+        Wavetable::new(&FACTORS_REF[4], sample_rate),
+        Wavetable::new(&FACTORS_REF[5], sample_rate),
+    ]
+}
 
-        let sample_rate = pd_sys::sys_getsr() as u32;
-        BANK = Some([
-            Wavetable::new(FACTORS_0_REF.as_ref().unwrap(), sample_rate),
-            Wavetable::new(FACTORS_1_REF.as_ref().unwrap(), sample_rate),
-            Wavetable::new(FACTORS_2_REF.as_ref().unwrap(), sample_rate),
-            Wavetable::new(FACTORS_3_REF.as_ref().unwrap(), sample_rate),
-            Wavetable::new(FACTORS_4_REF.as_ref().unwrap(), sample_rate),
-            Wavetable::new(FACTORS_5_REF.as_ref().unwrap(), sample_rate),
-        ]);
-    }
+/// All versions of each waveform, with fewer and fewer harmonics.
+pub fn factors() -> &'static [FactorsRef] {
+    &FACTORS_REF[..]
 }
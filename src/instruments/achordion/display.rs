@@ -0,0 +1,96 @@
+//! Drawing of the waveform the oscillators are morphed to into an array of
+//! Pure Data, e.g. a graph in the achordion abstraction.
+//!
+//! The instrument does not expose its waveform. A fresh instrument with the
+//! same bank and wavetable plays its solo instead, and one period of it is
+//! drawn. The pitch is low, so the period spans hundreds of samples and
+//! the wavetables used for it keep nearly all their harmonics.
+
+use achordion_lib::instrument::Instrument;
+
+use crate::clock::Clock;
+use crate::wrapper;
+
+use super::bank;
+use super::voices;
+use super::BANK;
+
+/// V/Oct of the played solo, on the root of the scale in every mode.
+const VOCT: f32 = 2.0;
+
+pub struct Display {
+    array: String,
+    /// Milliseconds between refreshes, zero to draw only once.
+    interval: f64,
+    clock: Box<dyn Clock>,
+    sample_rate: u32,
+    /// Bank and wavetable drawn the last time. Refreshes are skipped until
+    /// they change.
+    drawn: Option<(usize, f32)>,
+}
+
+impl Display {
+    pub fn new(clock: Box<dyn Clock>, sample_rate: u32) -> Self {
+        Self {
+            array: String::new(),
+            interval: 0.0,
+            clock,
+            sample_rate,
+            drawn: None,
+        }
+    }
+
+    /// Draw into another array, refreshing it every given number of
+    /// milliseconds.
+    pub fn show(&mut self, array: &str, interval: f32) {
+        self.array = array.to_string();
+        self.interval = f64::from(interval.max(0.0));
+        self.drawn = None;
+        self.clock.unset();
+    }
+
+    /// Draw the waveform if it changed since the last time and schedule the
+    /// next refresh. A missing array stops the refresh.
+    pub fn refresh(&mut self, bank: usize, wavetable: f32) -> Result<(), String> {
+        if self.drawn != Some((bank, wavetable)) {
+            let period = self.sample_rate as f32 / voices::frequency(VOCT);
+            let samples = self.play(bank, wavetable, period.ceil() as usize + 1);
+            if !wrapper::draw_array(&self.array, |position| {
+                // Between samples, the waveform is interpolated linearly.
+                let position = position * period;
+                let (i, mix) = (position as usize, position.fract());
+                samples[i] + (samples[i + 1] - samples[i]) * mix
+            }) {
+                self.interval = 0.0;
+                return Err(format!("array {} not found", self.array));
+            }
+            self.drawn = Some((bank, wavetable));
+        }
+        if self.interval > 0.0 {
+            self.clock.delay(self.interval);
+        }
+        Ok(())
+    }
+
+    /// The given number of samples of the solo, starting with the first
+    /// period of its oscillator.
+    fn play(&self, bank: usize, wavetable: f32, length: usize) -> Vec<f32> {
+        const BUFFER_LEN: usize = 32;
+
+        let banks = bank::wavetable_banks(self.sample_rate);
+        let mut instrument = Instrument::new(&banks[..], self.sample_rate);
+        instrument.set_wavetable_bank(BANK.value(BANK.names[bank], &(0.0..=1.0)).unwrap());
+        instrument.set_wavetable(wavetable);
+        instrument.set_detune(0.0);
+        instrument.set_scale_root_voct(0.0);
+        instrument.set_solo_voct(Some(VOCT));
+
+        let mut solo = vec![0.0; length.next_multiple_of(BUFFER_LEN)];
+        let mut chord = [0.0; BUFFER_LEN];
+        for buffer in solo.chunks_mut(BUFFER_LEN) {
+            instrument.populate(buffer, &mut chord);
+        }
+        solo.truncate(length);
+        solo
+    }
+}
@@ -1,169 +1,659 @@
+mod arpeggiator;
 mod bank;
+mod display;
+mod quantizer;
+mod voices;
 
-use std::os::raw::{c_int, c_void};
+use std::os::raw::c_void;
+use std::path::PathBuf;
 
 use achordion_lib::instrument::Instrument;
 
-use bank::WAVETABLE_BANKS;
-
-use crate::{cstr, log};
-
-static mut CLASS: Option<*mut pd_sys::_class> = None;
-
-#[repr(C)]
-struct Class<'a> {
-    pd_obj: pd_sys::t_object,
-    solo_outlet: *mut pd_sys::_outlet,
-    chord_outlet: *mut pd_sys::_outlet,
-    instrument: Instrument<'a>,
-    signal_dummy: f32,
+use crate::choice::Choice;
+use crate::clock::PdClock;
+use crate::instruments::fade::Fade;
+use crate::instruments::harmony::{self, Harmony};
+use crate::log::Logger;
+use crate::wrapper::{self, Atom, Class, Context, Parameter, PdClass};
+
+use self::arpeggiator::Arpeggiator;
+use self::display::Display;
+use self::voices::Voices;
+
+pub(crate) const BANK: Choice = Choice {
+    selector: "bank",
+    parameter: "wavetable_bank",
+    names: &["perfect", "harsh", "soft", "sins"],
+};
+
+pub(crate) const STYLE: Choice = Choice {
+    selector: "style",
+    parameter: "style",
+    names: &["thirds", "fifths", "octaves"],
+};
+
+pub use self::quantizer::achordion_quantizer_tilde_setup;
+
+pub(crate) struct Achordion {
+    instrument: Instrument<'static>,
+    /// Playing instead of the instrument while a scale defined by the user
+    /// is selected.
+    voices: Voices,
+    active: Fade,
+    level: Fade,
+    logger: Logger,
+    directory: PathBuf,
+    /// Mirror of the scale and chord settings of the instrument, which may
+    /// be replaced by a scale defined by the user.
+    harmony: Harmony,
+    /// Pitches as received, to be quantized again once the scale changes.
+    solo: Option<f32>,
+    chord_root: Option<f32>,
+    arpeggiator: Arpeggiator,
+    /// Whether the arpeggio plays on the solo voice, rather than only
+    /// through its own outlet.
+    arp_solo: bool,
+    arp_voct: f32,
+    wavetable_bank: usize,
+    wavetable: f32,
+    /// Created on the first `display`, only objects living in Pure Data
+    /// have arrays to draw into.
+    display: Option<Display>,
+    owner: *mut c_void,
+    sample_rate: u32,
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn achordion_tilde_setup() {
     bank::setup();
 
-    let class = create_class();
-
-    CLASS = Some(class);
-
-    register_dsp_method!(
-        class,
-        receiver = Class,
-        dummy_offset = offset_of!(Class => signal_dummy),
-        number_of_inlets = 1,
-        number_of_outlets = 3,
-        callback = perform
-    );
-
-    register_float_method(class, "solo", set_solo);
-    register_float_method(class, "float", set_chord_root);
-    register_float_method(class, "chord_degrees", set_chord_degrees);
-    register_float_method(class, "scale_mode", set_scale_mode);
-    register_float_method(class, "scale_root", set_scale_root);
-    register_float_method(class, "wavetable_bank", set_wavetable_bank);
-    register_float_method(class, "wavetable", set_wavetable);
-    register_float_method(class, "detune", set_detune);
-    register_float_method(class, "style", set_style);
+    wrapper::register_class::<Achordion>();
 }
 
-unsafe fn create_class() -> *mut pd_sys::_class {
-    log::info("[achordion~] initializing");
-
-    pd_sys::class_new(
-        pd_sys::gensym(cstr::cstr("achordion~").as_ptr()),
-        Some(new),
-        None,
-        std::mem::size_of::<Class>(),
-        pd_sys::CLASS_DEFAULT as i32,
-        0,
-    )
-}
+impl PdClass for Achordion {
+    const NAME: &'static str = "achordion~";
+    // The second inlet takes triggers of the arpeggiator.
+    const SIGNAL_INLETS: usize = 2;
+    const SIGNAL_INLET_FALLBACKS: &'static [Option<f32>] = &[Some(0.0)];
+    // Mix, solo, chord and V/Oct of the arpeggio.
+    const SIGNAL_OUTLETS: usize = 4;
+
+    const PARAMETERS: &'static [Parameter<Self>] = &[
+        parameter!("bypass", Achordion::set_bypass, Setting),
+        parameter!("mute", Achordion::set_mute, Setting),
+        parameter!("solo", Achordion::set_solo, Input, 0.0..=10.0),
+        parameter!("float", Achordion::set_chord_root, Input, 0.0..=10.0),
+        parameter!("chord_degrees", Achordion::set_chord_degrees, Discrete),
+        parameter!("scale_mode", Achordion::set_scale_mode, Discrete),
+        parameter!(
+            "scale_root",
+            Achordion::set_scale_root,
+            Continuous,
+            0.0..=20.0
+        ),
+        parameter!("wavetable_bank", Achordion::set_wavetable_bank, Discrete),
+        parameter!("wavetable", Achordion::set_wavetable),
+        parameter!("detune", Achordion::set_detune),
+        parameter!("style", Achordion::set_style, Discrete),
+        parameter!("arp", Achordion::set_arp, Discrete),
+        parameter!(
+            "arp_octaves",
+            Achordion::set_arp_octaves,
+            Discrete,
+            1.0..=4.0
+        ),
+        parameter!("arp_solo", Achordion::set_arp_solo, Setting),
+        parameter!("seed", Achordion::set_seed, Setting),
+    ];
+
+    const CHOICES: &'static [Choice] = &[BANK, harmony::MODE, STYLE, arpeggiator::DIRECTION];
+
+    fn new(context: &mut Context) -> Result<Self, String> {
+        let sample_rate = context.sample_rate() as u32;
+        let banks = bank::wavetable_banks(sample_rate);
+        Ok(Self {
+            instrument: Instrument::new(&banks[..], sample_rate),
+            voices: Voices::new(context.sample_rate()),
+            active: Fade::new(context.sample_rate(), true),
+            level: Fade::new(context.sample_rate(), true),
+            logger: context.logger(),
+            directory: context.directory().to_path_buf(),
+            harmony: Harmony::default(),
+            solo: None,
+            chord_root: None,
+            arpeggiator: Arpeggiator::default(),
+            arp_solo: true,
+            arp_voct: 0.0,
+            wavetable_bank: 0,
+            wavetable: 0.0,
+            display: None,
+            owner: context.owner(),
+            sample_rate,
+        })
+    }
 
-unsafe extern "C" fn new() -> *mut c_void {
-    let class = pd_sys::pd_new(CLASS.unwrap()) as *mut Class;
+    fn register(class: &mut Class<Self>) {
+        class.add_gimme_method("scale", gimme_method!(Achordion::define_scale));
+        class.add_gimme_method("scale_mode_symbol", gimme_method!(Achordion::select_scale));
+        class.add_gimme_method("tuning", gimme_method!(Achordion::load_tuning));
+        class.add_gimme_method("display", gimme_method!(Achordion::display));
+    }
 
-    let sample_rate = pd_sys::sys_getsr() as u32;
-    let instrument = Instrument::new(&WAVETABLE_BANKS.as_ref().unwrap()[..], sample_rate);
+    fn perform(&mut self, _number_of_frames: usize, inlets: &[&[f32]], outlets: &mut [&mut [f32]]) {
+        const BUFFER_LEN: usize = 32;
 
-    (*class).instrument = instrument;
+        let mut buffer_solo = [0.0; BUFFER_LEN];
+        let mut buffer_chord = [0.0; BUFFER_LEN];
 
-    pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).solo_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).chord_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
+        // The oscillators are not running at all while bypassed.
+        if self.active.is_off() {
+            for outlet in outlets.iter_mut() {
+                outlet.fill(0.0);
+            }
+            return;
+        }
 
-    class as *mut c_void
+        // Blocks split by the wrapper may be of any length.
+        let number_of_frames = outlets[0].len();
+        for start in (0..number_of_frames).step_by(BUFFER_LEN) {
+            let length = BUFFER_LEN.min(number_of_frames - start);
+            let buffer_solo = &mut buffer_solo[..length];
+            let buffer_chord = &mut buffer_chord[..length];
+
+            // Triggers are taken once per chunk, the oscillators cannot
+            // change pitch within one.
+            let triggers = inlets[1][start..start + length]
+                .iter()
+                .filter(|x| self.arpeggiator.detect(**x))
+                .count();
+            if triggers > 0 && self.arpeggiator.is_on() {
+                for _ in 0..triggers {
+                    self.arpeggiate();
+                }
+                if self.arp_solo {
+                    self.play_solo(Some(self.arp_voct));
+                }
+            }
+
+            if self.harmony.is_custom() {
+                self.voices.populate(
+                    bank::factors(self.wavetable_bank),
+                    self.wavetable,
+                    buffer_solo,
+                    buffer_chord,
+                );
+            } else {
+                self.instrument.populate(buffer_solo, buffer_chord);
+            }
+
+            if !self.active.is_on() || !self.level.is_on() {
+                for (solo, chord) in buffer_solo.iter_mut().zip(buffer_chord.iter_mut()) {
+                    let gain = self.active.next() * self.level.next();
+                    *solo *= gain;
+                    *chord *= gain;
+                }
+            }
+
+            for i in 0..length {
+                outlets[1][start + i] = buffer_solo[i];
+                outlets[2][start + i] = buffer_chord[i];
+                outlets[0][start + i] = (outlets[1][start + i] + outlets[2][start + i]) / 2.0;
+            }
+            outlets[3][start..start + length].fill(self.arp_voct);
+        }
+    }
 }
 
-unsafe fn register_float_method(
-    class: *mut pd_sys::_class,
-    symbol: &str,
-    method: unsafe extern "C" fn(*mut Class, pd_sys::t_float),
-) {
-    pd_sys::class_addmethod(
-        class,
-        Some(std::mem::transmute::<
-            unsafe extern "C" fn(*mut Class, pd_sys::t_float),
-            _,
-        >(method)),
-        pd_sys::gensym(cstr::cstr(symbol).as_ptr()),
-        pd_sys::t_atomtype::A_FLOAT,
-        0,
-    );
-}
+impl Achordion {
+    // Fades out to silence and stops the oscillators.
+    fn set_bypass(&mut self, value: f32) {
+        self.active.set(value < 0.5);
+    }
 
-unsafe extern "C" fn set_solo(class: *mut Class, value: pd_sys::t_float) {
-    if value < 0.1 {
-        (*class).instrument.set_solo_voct(None);
-    } else {
-        (*class)
-            .instrument
-            .set_solo_voct(Some(value.clamp(0.0, 10.0)));
+    // Fades out to silence, while the oscillators keep running.
+    fn set_mute(&mut self, value: f32) {
+        self.level.set(value < 0.5);
     }
-}
 
-unsafe extern "C" fn set_chord_root(class: *mut Class, value: pd_sys::t_float) {
-    (*class)
-        .instrument
-        .set_chord_root_linear(Some(value.clamp(0.0, 10.0)));
-}
+    fn set_solo(&mut self, value: f32) {
+        self.solo = Some(value);
+        if self.arp_plays_solo() {
+            return;
+        }
+        if value < 0.1 {
+            self.play_solo(None);
+        } else {
+            let voct = self.quantize(value.clamp(0.0, 10.0));
+            self.play_solo(Some(voct));
+        }
+    }
 
-unsafe extern "C" fn set_chord_degrees(class: *mut Class, value: pd_sys::t_float) {
-    (*class).instrument.set_chord_degrees(value.clamp(0.0, 1.0));
-}
+    fn set_chord_root(&mut self, value: f32) {
+        self.chord_root = Some(value);
+        if self.harmony.is_custom() {
+            self.play_chord();
+        } else {
+            self.instrument
+                .set_chord_root_linear(Some(value.clamp(0.0, 10.0)));
+        }
+    }
 
-unsafe extern "C" fn set_scale_mode(class: *mut Class, value: pd_sys::t_float) {
-    (*class)
-        .instrument
-        .set_scale_mode(value.clamp(0.0, 1.0), false);
-}
+    fn set_chord_degrees(&mut self, value: f32) {
+        self.instrument.set_chord_degrees(value.clamp(0.0, 1.0));
+        self.harmony.set_chord_degrees(value);
+        if self.harmony.is_custom() {
+            self.play_chord();
+        }
+    }
 
-unsafe extern "C" fn set_scale_root(class: *mut Class, value: pd_sys::t_float) {
-    (*class)
-        .instrument
-        .set_scale_root_voct(value.clamp(0.0, 20.0));
-}
+    fn set_scale_mode(&mut self, value: f32) {
+        self.instrument.set_scale_mode(value.clamp(0.0, 1.0), false);
+        let was_custom = self.harmony.is_custom();
+        self.harmony.set_scale_mode(value);
+        if was_custom {
+            self.requantize();
+        }
+    }
 
-unsafe extern "C" fn set_wavetable_bank(class: *mut Class, value: pd_sys::t_float) {
-    (*class)
-        .instrument
-        .set_wavetable_bank(value.clamp(0.0, 1.0));
-}
+    fn set_scale_root(&mut self, value: f32) {
+        let voct = value.clamp(0.0, 20.0);
+        self.instrument.set_scale_root_voct(voct);
+        self.harmony.set_scale_root(voct);
+        if self.harmony.is_custom() {
+            self.requantize();
+        }
+    }
 
-unsafe extern "C" fn set_wavetable(class: *mut Class, value: pd_sys::t_float) {
-    (*class).instrument.set_wavetable(value.clamp(0.0, 1.0));
-}
+    fn set_wavetable_bank(&mut self, value: f32) {
+        let value = value.clamp(0.0, 1.0);
+        self.instrument.set_wavetable_bank(value);
+        self.wavetable_bank = BANK.index(value, &(0.0..=1.0));
+    }
 
-unsafe extern "C" fn set_detune(class: *mut Class, value: pd_sys::t_float) {
-    (*class).instrument.set_detune(value.clamp(0.0, 1.0));
-}
+    fn set_wavetable(&mut self, value: f32) {
+        self.wavetable = value.clamp(0.0, 1.0);
+        self.instrument.set_wavetable(self.wavetable);
+    }
+
+    fn set_detune(&mut self, value: f32) {
+        self.instrument.set_detune(value.clamp(0.0, 1.0));
+        self.voices.set_detune(value.clamp(0.0, 1.0));
+    }
+
+    fn set_style(&mut self, value: f32) {
+        self.instrument.set_style(value.clamp(0.0, 1.0));
+    }
+
+    fn set_arp(&mut self, value: f32) {
+        let was_playing = self.arp_plays_solo();
+        self.arpeggiator.set_direction(value);
+        if was_playing && !self.arp_plays_solo() {
+            self.requantize();
+        }
+    }
+
+    fn set_arp_octaves(&mut self, value: f32) {
+        self.arpeggiator.set_octaves(value);
+    }
+
+    // Makes the random arpeggio reproducible.
+    fn set_seed(&mut self, value: f32) {
+        self.arpeggiator.set_seed(value as u64);
+    }
+
+    // Once the arpeggio stops playing on the solo voice, the voice gets
+    // back to the pitch it was given.
+    fn set_arp_solo(&mut self, value: f32) {
+        let was_playing = self.arp_plays_solo();
+        self.arp_solo = value > 0.5;
+        if was_playing && !self.arp_plays_solo() {
+            self.requantize();
+        }
+    }
+
+    fn arp_plays_solo(&self) -> bool {
+        self.arpeggiator.is_on() && self.arp_solo
+    }
+
+    /// Move the arpeggio to the next tone of the chord.
+    fn arpeggiate(&mut self) {
+        let root = self.chord_root.unwrap_or(0.0).clamp(0.0, 10.0);
+        let index = self
+            .arpeggiator
+            .advance(self.harmony.number_of_chord_tones());
+        self.arp_voct = self.harmony.chord_tone(root, index).clamp(0.0, 10.0);
+    }
+
+    fn define_scale(&mut self, arguments: &[Atom]) {
+        self.log(harmony::define_scale(arguments));
+    }
+
+    /// Use a scale defined by the user instead of a mode of the instrument.
+    fn select_scale(&mut self, arguments: &[Atom]) {
+        match self.harmony.select_scale(arguments) {
+            Ok(()) => self.requantize(),
+            Err(message) => self.log(Err(message)),
+        }
+    }
+
+    fn load_tuning(&mut self, arguments: &[Atom]) {
+        let result = self.harmony.load_tuning(&self.directory, arguments);
+        if result.is_ok() {
+            self.requantize();
+        }
+        self.log(result);
+    }
+
+    /// Draw the current waveform into an array, `display <array>
+    /// [interval]`, refreshing it every interval of milliseconds if given.
+    fn display(&mut self, arguments: &[Atom]) {
+        let (array, interval) = match arguments {
+            [Atom::Symbol(array)] => (*array, 0.0),
+            [Atom::Symbol(array), Atom::Float(interval)] => (*array, *interval),
+            _ => {
+                self.logger.error(
+                    "[achordion~] display expects an array and an optional interval in milliseconds",
+                );
+                return;
+            }
+        };
+        let (owner, sample_rate) = (self.owner, self.sample_rate);
+        self.display
+            .get_or_insert_with(|| {
+                let clock =
+                    unsafe { PdClock::new(owner, bang_method!(Achordion::refresh_display)) };
+                Display::new(Box::new(clock), sample_rate)
+            })
+            .show(array, interval);
+        self.refresh_display();
+    }
+
+    fn refresh_display(&mut self) {
+        if let Some(display) = &mut self.display {
+            if let Err(message) = display.refresh(self.wavetable_bank, self.wavetable) {
+                self.logger.error(&format!("[achordion~] {}", message));
+            }
+        }
+    }
 
-unsafe extern "C" fn set_style(class: *mut Class, value: pd_sys::t_float) {
-    (*class).instrument.set_style(value.clamp(0.0, 1.0));
+    /// Report the outcome of a message handled by the harmony.
+    fn log(&self, result: Result<String, String>) {
+        match result {
+            Ok(message) => self.logger.info(&format!("[achordion~] {}", message)),
+            Err(message) => self.logger.error(&format!("[achordion~] {}", message)),
+        }
+    }
+
+    /// The instrument itself only knows its own modes, so with a scale of the
+    /// user selected, pitches are snapped to it here.
+    fn quantize(&self, voct: f32) -> f32 {
+        if self.harmony.is_custom() {
+            self.harmony.quantize(voct)
+        } else {
+            voct
+        }
+    }
+
+    /// Pass the quantized pitch of the solo to whichever plays it.
+    fn play_solo(&mut self, voct: Option<f32>) {
+        if self.harmony.is_custom() {
+            self.voices.set_solo(voct);
+        } else {
+            self.instrument.set_solo_voct(voct);
+        }
+    }
+
+    /// Stack the chord of the scale defined by the user on its root.
+    fn play_chord(&mut self) {
+        let Some(root) = self.chord_root else {
+            return;
+        };
+        let root = root.clamp(0.0, 10.0);
+        let harmony = &self.harmony;
+        self.voices
+            .set_chord((0..harmony.number_of_chord_tones()).map(|i| harmony.chord_tone(root, i)));
+    }
+
+    fn requantize(&mut self) {
+        if let Some(solo) = self.solo {
+            self.set_solo(solo);
+        }
+        if self.arp_plays_solo() {
+            self.play_solo(Some(self.arp_voct));
+        }
+        if let Some(chord_root) = self.chord_root {
+            self.set_chord_root(chord_root);
+        }
+    }
 }
 
-fn perform(
-    class: &mut Class,
-    _number_of_frames: usize,
-    _inlets: &[&mut [pd_sys::t_float]],
-    outlets: &mut [&mut [pd_sys::t_float]],
-) {
-    const BUFFER_LEN: usize = 32;
-    assert!(outlets[0].len() % BUFFER_LEN == 0);
-
-    let mut buffer_solo = [0.0; BUFFER_LEN];
-    let mut buffer_chord = [0.0; BUFFER_LEN];
-
-    for chunk_index in 0..outlets[0].len() / BUFFER_LEN {
-        class
-            .instrument
-            .populate(&mut buffer_solo[..], &mut buffer_chord[..]);
-
-        let start = chunk_index * BUFFER_LEN;
-        for i in 0..BUFFER_LEN {
-            outlets[1][start + i] = buffer_solo[i];
-            outlets[2][start + i] = buffer_chord[i];
-            outlets[0][start + i] = (outlets[1][start + i] + outlets[2][start + i]) / 2.0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::instruments::harmony::MODE;
+    use crate::instruments::scale::{self, Scale};
+
+    fn semitones(frequency: f32) -> f32 {
+        let octaves = (frequency / voices::frequency(0.0)).log2();
+        (octaves * 12.0 * 1000.0).round() / 1000.0
+    }
+
+    fn played(achordion: &Achordion) -> (f32, Vec<f32>) {
+        let (solo, chord) = achordion.voices.frequencies();
+        (
+            semitones(solo.unwrap()),
+            chord.into_iter().map(semitones).collect(),
+        )
+    }
+
+    #[test]
+    fn it_plays_chords_of_scales_defined_by_the_user() {
+        let mut achordion = Achordion::new(&mut Context::new(48000.0)).unwrap();
+        let hirajoshi = Scale::from_semitones(&[0.0, 2.0, 3.0, 7.0, 8.0]).unwrap();
+        scale::define("unit-hirajoshi", hirajoshi);
+
+        achordion.select_scale(&[Atom::Symbol("unit-hirajoshi")]);
+        achordion.set_chord_degrees(0.4);
+        achordion.set_chord_root(2.0 + 4.9 / 12.0);
+        achordion.set_solo(3.0 + 5.4 / 12.0);
+        // Triad of the third degree, Eb Ab D, is not a chord of any mode.
+        assert_eq!(played(&achordion), (43.0, vec![27.0, 32.0, 38.0]));
+
+        achordion.set_scale_root(1.0 / 12.0);
+        assert_eq!(played(&achordion), (40.0, vec![28.0, 33.0, 39.0]));
+
+        achordion.set_chord_degrees(0.0);
+        assert_eq!(played(&achordion).1, vec![28.0]);
+
+        // Modes are played by the instrument again.
+        achordion.set_scale_mode(0.0);
+        assert!(!achordion.harmony.is_custom());
+    }
+
+    /// Power of the signal at the frequency, by the Goertzel algorithm.
+    fn power(signal: &[f32], frequency: f32, sample_rate: f32) -> f64 {
+        let omega = std::f64::consts::TAU * frequency as f64 / sample_rate as f64;
+        let coefficient = 2.0 * omega.cos();
+        let (mut previous, mut before) = (0.0, 0.0);
+        for x in signal {
+            let current = *x as f64 + coefficient * previous - before;
+            before = previous;
+            previous = current;
+        }
+        previous * previous + before * before - coefficient * previous * before
+    }
+
+    /// Whether each of the frequencies stands out of neighbouring semitones
+    /// that are not among them.
+    fn sounds(signal: &[f32], frequencies: &[f32], sample_rate: f32) -> bool {
+        let semitone = 2.0_f32.powf(1.0 / 12.0);
+        frequencies.iter().all(|frequency| {
+            let neighbours = [frequency / semitone, frequency * semitone]
+                .into_iter()
+                .filter(|n| frequencies.iter().all(|f| (f / n).log2().abs() > 1e-3))
+                .map(|n| power(signal, n, sample_rate))
+                .fold(0.0, f64::max);
+            power(signal, *frequency, sample_rate) > 4.0 * neighbours
+        })
+    }
+
+    #[test]
+    fn it_plays_scales_defined_by_the_user_like_the_instrument_plays_its_modes() {
+        const IONIAN: [f32; 7] = [0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 11.0];
+        let sample_rate = 48000.0;
+
+        for mode in 0..IONIAN.len() {
+            let steps: Vec<f32> = (0..IONIAN.len())
+                .map(|i| IONIAN[(i + mode) % IONIAN.len()] - IONIAN[mode])
+                .collect();
+            let name = format!("unit-mode-{}", mode);
+            scale::define(&name, Scale::from_semitones(&steps).unwrap());
+
+            for chord in 0..harmony::CHORDS.len() {
+                let mut voices = Achordion::new(&mut Context::new(sample_rate)).unwrap();
+                let mut instrument = Achordion::new(&mut Context::new(sample_rate)).unwrap();
+                voices.select_scale(&[Atom::Symbol(&name)]);
+                instrument.set_scale_mode(MODE.value(MODE.names[mode], &(0.0..=1.0)).unwrap());
+
+                // Pitches off the scale, closer to one of its steps.
+                let semitone = (mode * harmony::CHORDS.len() + chord) % 12;
+                for achordion in [&mut voices, &mut instrument] {
+                    achordion
+                        .set_chord_degrees((chord as f32 + 0.5) / harmony::CHORDS.len() as f32);
+                    achordion.set_solo(3.0 + (semitone as f32 + 0.3) / 12.0);
+                    achordion.set_chord_root(2.0 + (semitone as f32 + 0.3) / 12.0);
+                }
+                let (solo, tones) = voices.voices.frequencies();
+                let solo = [solo.unwrap()];
+
+                for achordion in [&mut voices, &mut instrument] {
+                    let mut played_solo = vec![0.0; 24000];
+                    let mut played_chord = vec![0.0; 24000];
+                    for (solo, chord) in played_solo.chunks_mut(32).zip(played_chord.chunks_mut(32))
+                    {
+                        if achordion.harmony.is_custom() {
+                            achordion
+                                .voices
+                                .populate(bank::factors(0), 0.0, solo, chord);
+                        } else {
+                            achordion.instrument.populate(solo, chord);
+                        }
+                    }
+                    assert!(
+                        sounds(&played_solo, &solo, sample_rate),
+                        "solo of mode {} is not {:?}",
+                        mode,
+                        solo
+                    );
+                    assert!(
+                        sounds(&played_chord, &tones, sample_rate),
+                        "chord {} of mode {} is not {:?}",
+                        chord,
+                        mode,
+                        tones
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_plays_the_same_pitches_as_the_harmony_of_every_mode_and_chord() {
+        let sample_rate = 48000.0;
+        for (i, mode) in MODE.names.iter().enumerate() {
+            for chord in 0..harmony::CHORDS.len() {
+                let mut achordion = Achordion::new(&mut Context::new(sample_rate)).unwrap();
+                achordion.set_scale_mode(MODE.value(mode, &(0.0..=1.0)).unwrap());
+                achordion.set_chord_degrees((chord as f32 + 0.5) / harmony::CHORDS.len() as f32);
+                // Pitches off the scale, closer to one of its steps.
+                let semitone = (i * harmony::CHORDS.len() + chord) % 12;
+                let solo = 3.0 + (semitone as f32 + 0.3) / 12.0;
+                let root = 2.0 + (semitone as f32 + 0.3) / 12.0;
+                achordion.set_solo(solo);
+                achordion.set_chord_root(root);
+
+                let mut played_solo = vec![0.0; 24000];
+                let mut played_chord = vec![0.0; 24000];
+                for (solo, chord) in played_solo.chunks_mut(32).zip(played_chord.chunks_mut(32)) {
+                    achordion.instrument.populate(solo, chord);
+                }
+
+                let harmony = &achordion.harmony;
+                let expected = [voices::frequency(harmony.quantize(solo))];
+                assert!(
+                    sounds(&played_solo, &expected, sample_rate),
+                    "solo {} of {} is not {:?}",
+                    solo,
+                    mode,
+                    expected
+                );
+                let expected: Vec<_> = (0..harmony.number_of_chord_tones())
+                    .map(|tone| voices::frequency(harmony.chord_tone(root, tone)))
+                    .collect();
+                assert!(
+                    sounds(&played_chord, &expected, sample_rate),
+                    "chord {} on {} of {} is not {:?}",
+                    chord,
+                    root,
+                    mode,
+                    expected
+                );
+            }
+        }
+    }
+
+    fn write_tuning(directory: &Path, name: &str, pitches: &[String]) {
+        let text = format!(
+            "! {}\n\n {}\n {}\n",
+            name,
+            pitches.len(),
+            pitches.join("\n ")
+        );
+        std::fs::write(directory.join(name), text).unwrap();
+    }
+
+    fn assert_ratio(a: f32, b: f32, ratio: f32) {
+        assert!((a / b - ratio).abs() < 1e-4, "{} / {} != {}", a, b, ratio);
+    }
+
+    #[test]
+    fn it_plays_pitches_of_microtonal_tunings() {
+        let mut achordion = Achordion::new(&mut Context::new(48000.0)).unwrap();
+        achordion.directory = std::env::temp_dir();
+
+        let edo19: Vec<_> = (1..=19)
+            .map(|step| format!("{:.5}", step as f32 * 1200.0 / 19.0))
+            .collect();
+        write_tuning(&achordion.directory, "unit-19edo.scl", &edo19);
+        achordion.load_tuning(&[Atom::Symbol("unit-19edo.scl")]);
+        achordion.set_chord_degrees(0.4);
+        achordion.set_chord_root(2.0);
+        achordion.set_solo(3.3);
+        let (solo, chord) = achordion.voices.frequencies();
+        // 0.3 of an octave is the closest to the 6th of 19 steps.
+        let step = 2.0_f32.powf(1.0 / 19.0);
+        assert_ratio(solo.unwrap(), voices::frequency(3.0), step.powi(6));
+        for (tone, steps) in chord.iter().zip([0, 2, 4]) {
+            assert_ratio(*tone, voices::frequency(2.0), step.powi(steps));
+        }
+
+        let just: Vec<_> = ["9/8", "5/4", "4/3", "3/2", "5/3", "15/8", "2"]
+            .map(String::from)
+            .to_vec();
+        write_tuning(&achordion.directory, "unit-just.scl", &just);
+        achordion.load_tuning(&[Atom::Symbol("unit-just.scl")]);
+        achordion.set_solo(2.0 + 7.0 / 12.0);
+        let (solo, chord) = achordion.voices.frequencies();
+        // Pure fifth and major third rather than tempered ones.
+        assert_ratio(solo.unwrap(), voices::frequency(2.0), 1.5);
+        assert_ratio(chord[1], chord[0], 1.25);
+        assert_ratio(chord[2], chord[0], 1.5);
+
+        for file in ["unit-19edo.scl", "unit-just.scl"] {
+            std::fs::remove_file(achordion.directory.join(file)).unwrap();
         }
     }
 }
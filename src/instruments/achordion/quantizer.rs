@@ -0,0 +1,256 @@
+//! Quantizer following the harmony of achordion~ without its oscillators.
+//!
+//! V/Oct of the first inlet is snapped either to the scale, or to tones of
+//! the chord built on the root given by the second inlet. Scale settings
+//! take the same messages as achordion~, and scales defined by the user are
+//! shared between both.
+
+use std::path::PathBuf;
+
+use crate::choice::Choice;
+use crate::instruments::harmony::{self, Harmony, Note};
+use crate::log::Logger;
+use crate::wrapper::{self, Atom, Class, Context, Parameter, PdClass};
+
+/// Input wavering around the boundary of two notes does not switch between
+/// them, as long as the current note is this close, a tenth of a semitone.
+const HYSTERESIS: f32 = 0.1 / 12.0;
+
+const TRIGGER_DURATION: f32 = 0.005;
+
+const QUANTIZE: Choice = Choice {
+    selector: "quantize",
+    parameter: "quantize",
+    names: &["scale", "chord"],
+};
+
+pub(crate) struct Quantizer {
+    harmony: Harmony,
+    chord: bool,
+    bypass: bool,
+    /// Held note, with the range of inputs snapping to it. Inputs within
+    /// the range are passed without consulting the harmony.
+    note: Option<Note>,
+    /// Root of the chord the range was found for.
+    root: f32,
+    /// Settings changed since the range was found.
+    stale: bool,
+    trigger_length: usize,
+    trigger_remaining: usize,
+    logger: Logger,
+    directory: PathBuf,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn achordion_quantizer_tilde_setup() {
+    wrapper::register_class::<Quantizer>();
+}
+
+impl PdClass for Quantizer {
+    const NAME: &'static str = "achordion-quantizer~";
+    // V/Oct to quantize and root of the chord.
+    const SIGNAL_INLETS: usize = 2;
+    const SIGNAL_INLET_FALLBACKS: &'static [Option<f32>] = &[Some(0.0)];
+    // Quantized V/Oct and trigger on every change of note.
+    const SIGNAL_OUTLETS: usize = 2;
+
+    const PARAMETERS: &'static [Parameter<Self>] = &[
+        parameter!("bypass", Quantizer::set_bypass, Setting),
+        parameter!("quantize", Quantizer::set_quantize, Discrete),
+        parameter!("chord_degrees", Quantizer::set_chord_degrees, Discrete),
+        parameter!("scale_mode", Quantizer::set_scale_mode, Discrete),
+        parameter!(
+            "scale_root",
+            Quantizer::set_scale_root,
+            Continuous,
+            0.0..=20.0
+        ),
+    ];
+
+    const CHOICES: &'static [Choice] = &[harmony::MODE, QUANTIZE];
+
+    fn new(context: &mut Context) -> Result<Self, String> {
+        Ok(Self {
+            harmony: Harmony::default(),
+            chord: false,
+            bypass: false,
+            note: None,
+            root: 0.0,
+            stale: false,
+            trigger_length: (context.sample_rate() * TRIGGER_DURATION) as usize,
+            trigger_remaining: 0,
+            logger: context.logger(),
+            directory: context.directory().to_path_buf(),
+        })
+    }
+
+    fn register(class: &mut Class<Self>) {
+        class.add_gimme_method("scale", gimme_method!(Quantizer::define_scale));
+        class.add_gimme_method("scale_mode_symbol", gimme_method!(Quantizer::select_scale));
+        class.add_gimme_method("tuning", gimme_method!(Quantizer::load_tuning));
+    }
+
+    fn perform(&mut self, _number_of_frames: usize, inlets: &[&[f32]], outlets: &mut [&mut [f32]]) {
+        if self.bypass {
+            outlets[0].copy_from_slice(inlets[0]);
+            outlets[1].fill(0.0);
+            return;
+        }
+
+        for i in 0..outlets[0].len() {
+            if self.follow(inlets[0][i], inlets[1][i]) {
+                self.trigger_remaining = self.trigger_length;
+            }
+            outlets[0][i] = self.note.map_or(0.0, |note| note.pitch);
+            outlets[1][i] = if self.trigger_remaining > 0 { 1.0 } else { 0.0 };
+            self.trigger_remaining = self.trigger_remaining.saturating_sub(1);
+        }
+    }
+}
+
+impl Quantizer {
+    fn set_bypass(&mut self, value: f32) {
+        self.bypass = value >= 0.5;
+    }
+
+    fn set_quantize(&mut self, value: f32) {
+        self.chord = QUANTIZE.index(value, &(0.0..=1.0)) == 1;
+        self.stale = true;
+    }
+
+    fn set_chord_degrees(&mut self, value: f32) {
+        self.harmony.set_chord_degrees(value);
+        self.stale = true;
+    }
+
+    fn set_scale_mode(&mut self, value: f32) {
+        self.harmony.set_scale_mode(value);
+        self.stale = true;
+    }
+
+    fn set_scale_root(&mut self, value: f32) {
+        self.harmony.set_scale_root(value.clamp(0.0, 20.0));
+        self.stale = true;
+    }
+
+    fn define_scale(&mut self, arguments: &[Atom]) {
+        self.log(harmony::define_scale(arguments));
+    }
+
+    fn select_scale(&mut self, arguments: &[Atom]) {
+        if let Err(message) = self.harmony.select_scale(arguments) {
+            self.log(Err(message));
+        }
+        self.stale = true;
+    }
+
+    fn load_tuning(&mut self, arguments: &[Atom]) {
+        let result = self.harmony.load_tuning(&self.directory, arguments);
+        self.log(result);
+        self.stale = true;
+    }
+
+    /// Report the outcome of a message handled by the harmony.
+    fn log(&self, result: Result<String, String>) {
+        match result {
+            Ok(message) => self
+                .logger
+                .info(&format!("[achordion-quantizer~] {}", message)),
+            Err(message) => self
+                .logger
+                .error(&format!("[achordion-quantizer~] {}", message)),
+        }
+    }
+
+    fn snap(&self, voct: f32) -> Note {
+        if self.chord {
+            self.harmony.chord_note(voct, self.root)
+        } else {
+            self.harmony.note(voct)
+        }
+    }
+
+    /// Move to the note closest to the input, returning true if it changed.
+    fn follow(&mut self, voct: f32, root: f32) -> bool {
+        let same = |a: f32, b: f32| (a - b).abs() < 1e-6;
+
+        if self.chord && root != self.root {
+            self.root = root;
+            self.stale = true;
+        }
+        let Some(note) = self.note else {
+            self.note = Some(self.snap(voct));
+            self.stale = false;
+            return true;
+        };
+        if self.stale {
+            // The held note stays if it is still a part of the changed
+            // harmony, its range may differ though.
+            self.stale = false;
+            let renewed = self.snap(note.pitch);
+            if same(renewed.pitch, note.pitch) && renewed.holds(voct, HYSTERESIS) {
+                self.note = Some(renewed);
+                return false;
+            }
+        } else if note.holds(voct, HYSTERESIS) {
+            return false;
+        }
+
+        let target = self.snap(voct);
+        self.note = Some(target);
+        !same(target.pitch, note.pitch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantizer() -> Quantizer {
+        Quantizer {
+            harmony: Harmony::default(),
+            chord: false,
+            bypass: false,
+            note: None,
+            root: 0.0,
+            stale: false,
+            trigger_length: 1,
+            trigger_remaining: 0,
+            logger: Logger::new(std::ptr::null()),
+            directory: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn it_holds_note_while_input_wavers_around_boundary() {
+        let mut quantizer = quantizer();
+        // Between E and F of C ionian.
+        let boundary = 4.5 / 12.0;
+
+        assert!(quantizer.follow(4.0 / 12.0, 0.0));
+        assert!(!quantizer.follow(boundary + HYSTERESIS / 2.0, 0.0));
+        assert!(!quantizer.follow(boundary - HYSTERESIS / 2.0, 0.0));
+        assert!(quantizer.follow(boundary + HYSTERESIS * 2.0, 0.0));
+        assert!((quantizer.note.unwrap().pitch - 5.0 / 12.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn it_moves_held_note_once_it_leaves_the_harmony() {
+        let mut quantizer = quantizer();
+        assert!(quantizer.follow(4.0 / 12.0, 0.0));
+        assert!(!quantizer.follow(4.0 / 12.0, 0.0));
+
+        // E is not a part of C phrygian.
+        quantizer.set_scale_mode(harmony::MODE.value("phrygian", &(0.0..=1.0)).unwrap());
+        assert!(quantizer.follow(3.9 / 12.0, 0.0));
+        assert!((quantizer.note.unwrap().pitch - 3.0 / 12.0).abs() < 1e-6);
+
+        // Eb is a tone of the triad on C, C Eb G, but not of the one on F,
+        // F Ab C.
+        quantizer.set_quantize(1.0);
+        quantizer.set_chord_degrees(0.4);
+        assert!(!quantizer.follow(3.0 / 12.0, 0.0));
+        assert!(quantizer.follow(3.0 / 12.0, 5.0 / 12.0));
+        assert!((quantizer.note.unwrap().pitch - 5.0 / 12.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,258 @@
+//! Oscillators playing scales defined by the user.
+//!
+//! The instrument stacks chords out of its own modes and tunes them to twelve
+//! equal steps of the octave. With a scale of the user selected, the solo and
+//! tones of the chord are played here instead, reading the same waveforms of
+//! the banks. The voicing of `style` is not applied to them, and detune is
+//! approximated by a second oscillator of each voice. Tests of `achordion~`
+//! check that they sound the pitches the instrument would, given a scale
+//! matching one of its modes.
+
+use super::bank::FactorsRef;
+
+/// Frequency of 0 V/Oct, C0.
+const C0: f32 = 16.351_598;
+
+/// Detune of 1 moves the second oscillator of each voice by a quarter tone.
+const DETUNE_RANGE: f32 = 0.5 / 12.0;
+
+/// As many tones as the richest chord of `chord_degrees` holds.
+pub const MAX_TONES: usize = 5;
+
+pub fn frequency(voct: f32) -> f32 {
+    C0 * voct.exp2()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Voice {
+    frequency: f32,
+    phases: [f32; 2],
+}
+
+impl Voice {
+    fn next(
+        &mut self,
+        factors: &[FactorsRef],
+        wavetable: f32,
+        detune: f32,
+        sample_rate: f32,
+    ) -> f32 {
+        let mut value = 0.0;
+        for (i, phase) in self.phases.iter_mut().enumerate() {
+            let frequency = self.frequency * (detune * DETUNE_RANGE * i as f32).exp2();
+            value += read(factors, band(frequency, sample_rate), wavetable, *phase);
+            *phase = (*phase + frequency / sample_rate).fract();
+        }
+        value / self.phases.len() as f32
+    }
+}
+
+pub struct Voices {
+    sample_rate: f32,
+    solo: Option<Voice>,
+    /// Voices past the number of tones are silent, keeping their phase
+    /// until the chord grows again.
+    chord: [Voice; MAX_TONES],
+    number_of_tones: usize,
+    detune: f32,
+}
+
+impl Voices {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            solo: None,
+            chord: [Voice::default(); MAX_TONES],
+            number_of_tones: 0,
+            detune: 0.0,
+        }
+    }
+
+    /// Pitch of the solo in V/Oct, silent with none.
+    pub fn set_solo(&mut self, voct: Option<f32>) {
+        match voct {
+            Some(voct) => self.solo.get_or_insert_with(Voice::default).frequency = frequency(voct),
+            None => self.solo = None,
+        }
+    }
+
+    /// Pitches of tones of the chord in V/Oct, tones past `MAX_TONES` are
+    /// dropped. Voices keep their phase, so changing chords does not click.
+    pub fn set_chord(&mut self, tones: impl Iterator<Item = f32>) {
+        self.number_of_tones = 0;
+        for (voice, voct) in self.chord.iter_mut().zip(tones) {
+            voice.frequency = frequency(voct);
+            self.number_of_tones += 1;
+        }
+    }
+
+    pub fn set_detune(&mut self, detune: f32) {
+        self.detune = detune;
+    }
+
+    /// Frequency of the solo and of tones of the chord.
+    #[cfg(test)]
+    pub fn frequencies(&self) -> (Option<f32>, Vec<f32>) {
+        (
+            self.solo.map(|voice| voice.frequency),
+            self.chord[..self.number_of_tones]
+                .iter()
+                .map(|voice| voice.frequency)
+                .collect(),
+        )
+    }
+
+    /// Play the waveforms of the bank, morphed by the wavetable. Tones of
+    /// the chord are mixed at equal level, keeping the chord within -1 to 1.
+    pub fn populate(
+        &mut self,
+        factors: &[FactorsRef],
+        wavetable: f32,
+        solo: &mut [f32],
+        chord: &mut [f32],
+    ) {
+        let (detune, sample_rate) = (self.detune, self.sample_rate);
+        for sample in solo.iter_mut() {
+            *sample = self.solo.as_mut().map_or(0.0, |voice| {
+                voice.next(factors, wavetable, detune, sample_rate)
+            });
+        }
+        let tones = &mut self.chord[..self.number_of_tones];
+        let gain = 1.0 / tones.len().max(1) as f32;
+        for sample in chord.iter_mut() {
+            *sample = tones
+                .iter_mut()
+                .map(|voice| voice.next(factors, wavetable, detune, sample_rate))
+                .sum::<f32>()
+                * gain;
+        }
+    }
+}
+
+/// Version of waveforms with harmonics up to half of the sample rate at the
+/// given frequency. The first one holds 512 harmonics.
+fn band(frequency: f32, sample_rate: f32) -> usize {
+    let relative = frequency * 1024.0 / sample_rate;
+    (relative.log2().ceil().max(0.0) as usize).min(10)
+}
+
+/// Value at the phase, from 0 to 1. Wavetable morphs through the waveforms,
+/// crossfading neighbouring ones.
+fn read(factors: &[FactorsRef], band: usize, wavetable: f32, phase: f32) -> f32 {
+    let position = wavetable.clamp(0.0, 1.0) * (factors.len() - 1) as f32;
+    let index = position as usize;
+    let mix = position - index as f32;
+
+    let read = |waveform: &[f32]| {
+        let position = phase.rem_euclid(1.0) * waveform.len() as f32;
+        let index = position as usize % waveform.len();
+        let mix = position - position.floor();
+        let next = (index + 1) % waveform.len();
+        waveform[index] + (waveform[next] - waveform[index]) * mix
+    };
+
+    let a = read(factors[index][band]);
+    match factors.get(index + 1) {
+        Some(next) if mix > 0.0 => a + (read(next[band]) - a) * mix,
+        _ => a,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine() -> FactorsRef {
+        let table: Vec<f32> = (0..1024)
+            .map(|i| (i as f32 / 1024.0 * std::f32::consts::TAU).sin())
+            .collect();
+        [Box::leak(table.into_boxed_slice()); 11]
+    }
+
+    /// Frequency of the signal lasting one second, from its rising zero
+    /// crossings.
+    fn measure(signal: &[f32]) -> usize {
+        signal
+            .windows(2)
+            .filter(|w| w[0] <= 0.0 && w[1] > 0.0)
+            .count()
+    }
+
+    #[test]
+    fn it_plays_solo_and_chord_at_frequencies_of_their_pitches() {
+        let factors = [sine()];
+        let mut voices = Voices::new(48000.0);
+        voices.set_solo(Some(4.0 + 9.0 / 12.0));
+        voices.set_chord([3.0 + 9.0 / 12.0].into_iter());
+
+        let mut solo = vec![0.0; 48000];
+        let mut chord = vec![0.0; 48000];
+        voices.populate(&factors, 0.0, &mut solo, &mut chord);
+        assert_eq!(measure(&solo), 440);
+        assert_eq!(measure(&chord), 220);
+
+        voices.set_chord([5.0, 5.0 + 4.0 / 12.0, 5.0 + 7.0 / 12.0].into_iter());
+        voices.populate(&factors, 0.0, &mut solo, &mut chord);
+        assert!(chord.iter().all(|x| x.abs() <= 1.0));
+        assert!(chord.iter().any(|x| x.abs() > 0.5));
+
+        voices.set_solo(None);
+        voices.set_chord(std::iter::empty());
+        voices.populate(&factors, 0.0, &mut solo, &mut chord);
+        assert!(solo.iter().all(|x| *x == 0.0));
+        assert!(chord.iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn it_morphs_between_neighbouring_waveforms() {
+        let saw: &'static [f32] = &[0.0, 0.5, 1.0, -1.0];
+        let square: &'static [f32] = &[1.0, 1.0, -1.0, -1.0];
+        let silence: &'static [f32] = &[0.0; 8];
+        let factors = [[saw; 11], [square; 11], [silence; 11]];
+        let read = |wavetable, phase| read(&factors, 0, wavetable, phase);
+
+        for (value, expected) in [
+            (read(0.0, 0.25), 0.5),
+            (read(0.0, 0.125), 0.25),
+            (read(0.0, 0.875), -0.5),
+            (read(0.5, 0.5), -1.0),
+            (read(0.25, 0.25), 0.75),
+            (read(0.75, 0.0), 0.5),
+            (read(1.0, 0.3), 0.0),
+        ] {
+            assert!((value - expected).abs() < 1e-5, "{} != {}", value, expected);
+        }
+    }
+
+    #[test]
+    fn it_keeps_phase_of_voices_when_the_chord_changes() {
+        let factors = [sine()];
+        let tones = [3.0, 3.5];
+        let mut solo = [0.0; 200];
+
+        let mut voices = Voices::new(48000.0);
+        voices.set_chord(tones.into_iter());
+        let mut whole = [0.0; 200];
+        voices.populate(&factors, 0.0, &mut solo, &mut whole);
+
+        let mut voices = Voices::new(48000.0);
+        voices.set_chord(tones.into_iter());
+        let mut first = [0.0; 100];
+        voices.populate(&factors, 0.0, &mut solo[..100], &mut first);
+        voices.set_chord([3.0].into_iter());
+        voices.set_chord(tones.into_iter());
+        let mut second = [0.0; 100];
+        voices.populate(&factors, 0.0, &mut solo[..100], &mut second);
+
+        assert_eq!(whole[..100], first);
+        assert_eq!(whole[100..], second);
+    }
+
+    #[test]
+    fn it_picks_waveforms_with_fewer_harmonics_for_higher_frequencies() {
+        assert_eq!(band(20.0, 48000.0), 0);
+        assert_eq!(band(48000.0 / 1024.0 * 3.0, 48000.0), 2);
+        assert_eq!(band(20000.0, 48000.0), 9);
+        assert_eq!(band(96000.0, 48000.0), 10);
+    }
+}
@@ -0,0 +1,45 @@
+/// Duration of the crossfade, in seconds.
+const FADE_TIME: f32 = 0.005;
+
+/// Gain moving linearly between 0 and 1, used to turn processing or output
+/// of an instrument on and off without clicks.
+pub struct Fade {
+    value: f32,
+    target: f32,
+    step: f32,
+}
+
+impl Fade {
+    pub fn new(sample_rate: f32, on: bool) -> Self {
+        let value = if on { 1.0 } else { 0.0 };
+        Self {
+            value,
+            target: value,
+            step: 1.0 / (FADE_TIME * sample_rate).max(1.0),
+        }
+    }
+
+    pub fn set(&mut self, on: bool) {
+        self.target = if on { 1.0 } else { 0.0 };
+    }
+
+    /// Gain of the next sample.
+    pub fn next(&mut self) -> f32 {
+        if self.value < self.target {
+            self.value = (self.value + self.step).min(self.target);
+        } else if self.value > self.target {
+            self.value = (self.value - self.step).max(self.target);
+        }
+        self.value
+    }
+
+    /// Faded out completely and staying so.
+    pub fn is_off(&self) -> bool {
+        self.value == 0.0 && self.target == 0.0
+    }
+
+    /// Faded in completely and staying so.
+    pub fn is_on(&self) -> bool {
+        self.value == 1.0 && self.target == 1.0
+    }
+}
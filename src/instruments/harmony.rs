@@ -0,0 +1,358 @@
+//! Scale and chord settings shared by modules working with pitch.
+//!
+//! The scale is either one of the modes, or a scale defined by the
+//! user. Chords are stacked out of degrees of the scale, starting from a
+//! root quantized to it. Messages defining and selecting scales are handled
+//! here too, so all objects following the harmony accept the same ones.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::choice::Choice;
+use crate::wrapper::Atom;
+
+use super::scala;
+use super::scale::{self, Scale};
+
+/// Modes selected by `scale_mode` or by name. The diatonic ones are followed
+/// by the chromatic scale, matching the eight cells of the mode selector of
+/// the patch editor.
+pub const MODE: Choice = Choice {
+    selector: "mode",
+    parameter: "scale_mode",
+    names: &[
+        "ionian",
+        "dorian",
+        "phrygian",
+        "lydian",
+        "mixolydian",
+        "aeolian",
+        "locrian",
+        "chromatic",
+    ],
+};
+
+const IONIAN: [f32; 7] = [0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 11.0];
+
+/// Degrees of chords selected by `chord_degrees`, from the plainest to the
+/// richest, counted from the root.
+pub const CHORDS: &[&[i32]] = &[
+    &[0],
+    &[0, 4],
+    &[0, 2, 4],
+    &[0, 3, 4],
+    &[0, 1, 4],
+    &[0, 2, 4, 6],
+    &[0, 2, 4, 6, 8],
+];
+
+lazy_static! {
+    static ref MODES: Vec<Scale> = {
+        let mut modes: Vec<Scale> = (0..IONIAN.len())
+            .map(|mode| {
+                let steps: Vec<f32> = (0..IONIAN.len())
+                    .map(|i| IONIAN[(i + mode) % IONIAN.len()] - IONIAN[mode])
+                    .collect();
+                Scale::from_semitones(&steps).unwrap()
+            })
+            .collect();
+        let chromatic: Vec<f32> = (0..12).map(|i| i as f32).collect();
+        modes.push(Scale::from_semitones(&chromatic).unwrap());
+        modes
+    };
+}
+
+/// Pitch an input was snapped to, with the range of inputs snapping to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub pitch: f32,
+    pub low: f32,
+    pub high: f32,
+}
+
+impl Note {
+    /// Ranges end half way to the neighbouring pitches.
+    fn between(below: f32, pitch: f32, above: f32) -> Self {
+        Self {
+            pitch,
+            low: (below + pitch) / 2.0,
+            high: (pitch + above) / 2.0,
+        }
+    }
+
+    /// Whether the input snaps to the note, or misses its range by no more
+    /// than the margin.
+    pub fn holds(&self, voct: f32, margin: f32) -> bool {
+        voct >= self.low - margin && voct <= self.high + margin
+    }
+}
+
+#[derive(Default)]
+pub struct Harmony {
+    /// Scale defined by the user, replacing the mode until it is set again.
+    custom: Option<Arc<Scale>>,
+    mode: usize,
+    root: f32,
+    chord: usize,
+}
+
+impl Harmony {
+    pub fn set_scale_mode(&mut self, value: f32) {
+        self.mode = MODE.index(value, &(0.0..=1.0));
+        self.custom = None;
+    }
+
+    pub fn set_custom_scale(&mut self, scale: Arc<Scale>) {
+        self.custom = Some(scale);
+    }
+
+    pub fn is_custom(&self) -> bool {
+        self.custom.is_some()
+    }
+
+    /// Root of the scale in V/Oct, only its position within the period of
+    /// the scale matters.
+    pub fn set_scale_root(&mut self, voct: f32) {
+        self.root = voct;
+    }
+
+    pub fn set_chord_degrees(&mut self, value: f32) {
+        self.chord = ((value.clamp(0.0, 1.0) * CHORDS.len() as f32) as usize).min(CHORDS.len() - 1);
+    }
+
+    pub fn scale(&self) -> &Scale {
+        self.custom.as_deref().unwrap_or(&MODES[self.mode])
+    }
+
+    pub fn quantize(&self, voct: f32) -> f32 {
+        self.scale().quantize(voct, self.root)
+    }
+
+    pub fn number_of_chord_tones(&self) -> usize {
+        CHORDS[self.chord].len()
+    }
+
+    /// Closest step of the scale, as `quantize`, with the range of inputs
+    /// snapping to it.
+    pub fn note(&self, voct: f32) -> Note {
+        let scale = self.scale();
+        let degree = scale.degree(voct, self.root);
+        let pitch = |degree| scale.pitch(degree, self.root);
+        Note::between(pitch(degree - 1), pitch(degree), pitch(degree + 1))
+    }
+
+    /// Closest tone of the chord built on the given root, in any period of
+    /// the scale, with the range of inputs snapping to it. It costs the same
+    /// regardless of the number of steps of the scale.
+    pub fn chord_note(&self, voct: f32, root: f32) -> Note {
+        let scale = self.scale();
+        let len = scale.number_of_steps() as i32;
+        let root = scale.degree(root, self.root);
+        let pitch = |degree| scale.pitch(degree, self.root);
+        let chord = CHORDS[self.chord];
+
+        let at_or_below = |degree: i32| {
+            chord
+                .iter()
+                .map(|c| degree - (degree - root - c).rem_euclid(len))
+                .max()
+                .unwrap()
+        };
+        let at_or_above = |degree: i32| {
+            chord
+                .iter()
+                .map(|c| degree + (root + c - degree).rem_euclid(len))
+                .min()
+                .unwrap()
+        };
+
+        // Tones of the chord enclosing the input.
+        let mut below = at_or_below(scale.degree(voct, self.root));
+        if pitch(below) > voct {
+            below = at_or_below(below - 1);
+        }
+        let above = at_or_above(below + 1);
+
+        if voct - pitch(below) <= pitch(above) - voct {
+            Note::between(pitch(at_or_below(below - 1)), pitch(below), pitch(above))
+        } else {
+            Note::between(pitch(below), pitch(above), pitch(at_or_above(above + 1)))
+        }
+    }
+
+    /// Use a scale defined by the user, `scale_mode_symbol <name>`.
+    pub fn select_scale(&mut self, arguments: &[Atom]) -> Result<(), String> {
+        let Some(Atom::Symbol(name)) = arguments.first() else {
+            return Err("scale_mode_symbol expects a name of a scale".to_string());
+        };
+        let scale = scale::find(name).ok_or_else(|| format!("unknown scale {}", name))?;
+        self.set_custom_scale(scale);
+        Ok(())
+    }
+
+    /// Load a microtonal tuning from `tuning <file.scl> [<file.kbm>]`, with
+    /// paths relative to the given directory, and select it. Other objects
+    /// can select it too, by the name of the file. Returns a message
+    /// describing the loaded tuning.
+    pub fn load_tuning(&mut self, directory: &Path, arguments: &[Atom]) -> Result<String, String> {
+        let files: Option<Vec<&str>> = arguments
+            .iter()
+            .map(|a| match a {
+                Atom::Symbol(file) => Some(*file),
+                Atom::Float(_) => None,
+            })
+            .collect();
+        let (scl, kbm) = match files.as_deref() {
+            Some([scl]) => (*scl, None),
+            Some([scl, kbm]) => (*scl, Some(*kbm)),
+            _ => return Err("tuning expects a .scl file and an optional .kbm file".to_string()),
+        };
+
+        let read = |file: &str| {
+            std::fs::read_to_string(directory.join(file))
+                .map_err(|e| format!("cannot read {}: {}", file, e))
+        };
+        let pitches = scala::parse_scale(&read(scl)?).map_err(|e| format!("{}: {}", scl, e))?;
+        let keyboard = match kbm {
+            Some(kbm) => {
+                Some(scala::parse_keyboard(&read(kbm)?).map_err(|e| format!("{}: {}", kbm, e))?)
+            }
+            None => None,
+        };
+        let tuning = scala::tuning(&pitches, keyboard.as_ref())?;
+
+        let name = Path::new(scl)
+            .file_stem()
+            .map_or(scl.into(), |s| s.to_string_lossy());
+        let message = format!(
+            "loaded tuning {} with {} steps",
+            name,
+            tuning.number_of_steps()
+        );
+        let tuning = Arc::new(tuning);
+        scale::define(&name, Arc::clone(&tuning));
+        self.set_custom_scale(tuning);
+        Ok(message)
+    }
+
+    /// V/Oct of a tone of the chord built on the given root. Indices past
+    /// the number of tones continue in the following periods of the scale.
+    pub fn chord_tone(&self, root: f32, index: usize) -> f32 {
+        let scale = self.scale();
+        let chord = CHORDS[self.chord];
+        let period = (index / chord.len()) as i32 * scale.number_of_steps() as i32;
+        let degree = scale.degree(root, self.root) + chord[index % chord.len()] + period;
+        scale.pitch(degree, self.root)
+    }
+}
+
+/// Define a scale by its name and semitones, e.g. `scale hirajoshi 0 2 3 7
+/// 8`, available to all objects. Returns a message confirming it.
+pub fn define_scale(arguments: &[Atom]) -> Result<String, String> {
+    let semitones: Option<Vec<f32>> = arguments.iter().skip(1).map(Atom::float).collect();
+    let (Some(Atom::Symbol(name)), Some(semitones)) = (arguments.first(), semitones) else {
+        return Err("scale expects a name followed by semitones".to_string());
+    };
+    scale::define(name, Scale::from_semitones(&semitones)?);
+    Ok(format!("defined scale {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    fn semitones(voct: f32) -> f32 {
+        (voct * 12.0 * 1000.0).round() / 1000.0
+    }
+
+    #[test]
+    fn it_builds_chords_out_of_the_mode() {
+        let mut harmony = Harmony::default();
+        harmony.set_chord_degrees(0.4);
+        assert_eq!(harmony.number_of_chord_tones(), 3);
+
+        let tones: Vec<_> = (0..4)
+            .map(|i| semitones(harmony.chord_tone(1.0, i)))
+            .collect();
+        assert_eq!(tones, vec![12.0, 16.0, 19.0, 24.0]);
+
+        harmony.set_scale_mode(MODE.value("aeolian", &(0.0..=1.0)).unwrap());
+        let tones: Vec<_> = (0..3)
+            .map(|i| semitones(harmony.chord_tone(1.0, i)))
+            .collect();
+        assert_eq!(tones, vec![12.0, 15.0, 19.0]);
+
+        // Root off the scale is quantized first, D of C aeolian is its
+        // second degree.
+        let tones: Vec<_> = (0..3)
+            .map(|i| semitones(harmony.chord_tone(1.0 + 2.1 / 12.0, i)))
+            .collect();
+        assert_eq!(tones, vec![14.0, 17.0, 20.0]);
+    }
+
+    #[test]
+    fn it_quantizes_to_closest_tone_of_the_chord() {
+        let mut harmony = Harmony::default();
+        harmony.set_chord_degrees(0.4);
+        let root = 2.0 / 12.0;
+
+        // D minor triad of C ionian, D F A.
+        for (input, output) in [
+            (0.0, 2.0),
+            (2.4, 2.0),
+            (3.6, 5.0),
+            (7.4, 9.0),
+            (11.6, 14.0),
+            (-4.6, -3.0),
+        ] {
+            assert_eq!(
+                semitones(harmony.chord_note(input / 12.0, root).pitch),
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn it_finds_closest_tone_of_the_chord_and_inputs_snapping_to_it() {
+        let mut harmony = Harmony::default();
+        harmony.set_scale_root(3.0 / 12.0);
+        let root = 2.0 + 4.6 / 12.0;
+
+        for mode in MODE.names {
+            harmony.set_scale_mode(MODE.value(mode, &(0.0..=1.0)).unwrap());
+            for chord in 0..CHORDS.len() {
+                harmony.set_chord_degrees((chord as f32 + 0.5) / CHORDS.len() as f32);
+                // All tones of the chord, starting periods below the input.
+                let tones: Vec<_> = (0..60).map(|i| harmony.chord_tone(root - 4.0, i)).collect();
+                // Off midpoints between tones, where either is the closest.
+                for i in 0..400 {
+                    let voct = i as f32 / 100.0 - 0.997;
+                    let closest = tones
+                        .iter()
+                        .copied()
+                        .min_by(|a, b| (a - voct).abs().total_cmp(&(b - voct).abs()))
+                        .unwrap();
+                    let note = harmony.chord_note(voct, root);
+                    assert_close(note.pitch, closest);
+                    assert!(note.holds(voct, 0.0), "{} {} {:?}", mode, chord, note);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_prefers_custom_scale_until_mode_is_set() {
+        let mut harmony = Harmony::default();
+        let hirajoshi = Scale::from_semitones(&[0.0, 2.0, 3.0, 7.0, 8.0]).unwrap();
+        harmony.set_custom_scale(Arc::new(hirajoshi));
+        assert_close(harmony.quantize(4.9 / 12.0), 3.0 / 12.0);
+
+        harmony.set_scale_mode(0.0);
+        assert!(!harmony.is_custom());
+        assert_close(harmony.quantize(4.9 / 12.0), 5.0 / 12.0);
+    }
+}
@@ -1,376 +1,428 @@
 // TODO: Implement setting of control
 // TODO: Implement hold button
 
-use core::mem::MaybeUninit;
 use rand::prelude::*;
-use std::os::raw::{c_int, c_void};
-use std::sync::Mutex;
+use rand::rngs::StdRng;
 
 use kaseta_control::{DesiredOutput, InputSnapshot, Store};
 use kaseta_dsp::processor::Processor;
 use kaseta_dsp::random::Random;
-use sirena::memory_manager::MemoryManager;
 
-use crate::{cstr, log};
+use crate::instruments::fade::Fade;
+use crate::pool;
+use crate::wrapper::{self, Class, Context, Parameter, PdClass};
 
-static mut CLASS: Option<*mut pd_sys::_class> = None;
-lazy_static! {
-    static ref MEMORY_MANAGER: Mutex<MemoryManager> = {
-        static mut MEMORY: [MaybeUninit<u32>; 48000 * 4 * 60 * 3] =
-            unsafe { MaybeUninit::uninit().assume_init() };
-        let memory_manager = MemoryManager::from(unsafe { &mut MEMORY[..] });
-        Mutex::new(memory_manager)
-    };
-}
+/// Length of the tape in seconds. At 48 kHz, four tapes fill the default
+/// size of the pool.
+const TAPE_LENGTH: usize = 60 * 3;
 
-struct KasetaRandom;
+struct KasetaRandom(StdRng);
+
+impl KasetaRandom {
+    fn from_entropy() -> Self {
+        Self(StdRng::from_entropy())
+    }
+
+    fn from_seed(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+}
 
 impl Random for KasetaRandom {
     fn normal(&mut self) -> f32 {
-        let mut rng = rand::thread_rng();
-        rng.gen()
+        self.0.gen()
     }
 }
 
-#[repr(C)]
-struct Class {
-    pd_obj: pd_sys::t_object,
-    right_outlet: *mut pd_sys::_outlet,
-    led_1_outlet: *mut pd_sys::_outlet,
-    led_2_outlet: *mut pd_sys::_outlet,
-    led_3_outlet: *mut pd_sys::_outlet,
-    led_4_outlet: *mut pd_sys::_outlet,
-    led_5_outlet: *mut pd_sys::_outlet,
-    led_6_outlet: *mut pd_sys::_outlet,
-    led_7_outlet: *mut pd_sys::_outlet,
-    led_8_outlet: *mut pd_sys::_outlet,
-    led_9_outlet: *mut pd_sys::_outlet,
-    impulse_outlet: *mut pd_sys::_outlet,
+pub(crate) struct Kaseta {
     input: InputSnapshot,
     control_connected: [bool; 4],
     output: DesiredOutput,
     cache: Store,
     processor: Processor,
-    signal_dummy: f32,
+    /// Tape memory of the processor, declared after it to be returned to the
+    /// pool only once the processor is dropped.
+    _memory: pool::Allocation,
+    random: KasetaRandom,
+    wet: Fade,
+    level: Fade,
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn kaseta_tilde_setup() {
-    let class = create_class();
-
-    CLASS = Some(class);
-
-    register_dsp_method!(
-        class,
-        receiver = Class,
-        dummy_offset = offset_of!(Class => signal_dummy),
-        number_of_inlets = 1,
-        number_of_outlets = 12,
-        callback = perform
-    );
-
-    register_bang_method(class, tick);
-    register_float_method(class, "control_1_connected", set_control_1_connected);
-    register_float_method(class, "control_2_connected", set_control_2_connected);
-    register_float_method(class, "control_3_connected", set_control_3_connected);
-    register_float_method(class, "control_4_connected", set_control_4_connected);
-    register_float_method(class, "control_1", set_control_1);
-    register_float_method(class, "control_2", set_control_2);
-    register_float_method(class, "control_3", set_control_3);
-    register_float_method(class, "control_4", set_control_4);
-    register_float_method(class, "button", set_button);
-    register_float_method(class, "pre_amp", set_pre_amp);
-    register_float_method(class, "dry_wet", set_dry_wet);
-    register_float_method(class, "drive", set_drive);
-    register_float_method(class, "bias", set_bias);
-    register_float_method(class, "wow_flutter", set_wow_flut);
-    register_float_method(class, "speed", set_speed);
-    register_float_method(class, "tone", set_tone);
-    register_float_method(class, "head_1_position", set_head_1_position);
-    register_float_method(class, "head_2_position", set_head_2_position);
-    register_float_method(class, "head_3_position", set_head_3_position);
-    register_float_method(class, "head_4_position", set_head_4_position);
-    register_float_method(class, "head_1_feedback", set_head_1_feedback);
-    register_float_method(class, "head_2_feedback", set_head_2_feedback);
-    register_float_method(class, "head_3_feedback", set_head_3_feedback);
-    register_float_method(class, "head_4_feedback", set_head_4_feedback);
-    register_float_method(class, "head_1_volume", set_head_1_volume);
-    register_float_method(class, "head_2_volume", set_head_2_volume);
-    register_float_method(class, "head_3_volume", set_head_3_volume);
-    register_float_method(class, "head_4_volume", set_head_4_volume);
-    register_float_method(class, "head_1_pan", set_head_1_pan);
-    register_float_method(class, "head_2_pan", set_head_2_pan);
-    register_float_method(class, "head_3_pan", set_head_3_pan);
-    register_float_method(class, "head_4_pan", set_head_4_pan);
-    register_float_method(class, "switch_1", set_option_1);
-    register_float_method(class, "switch_2", set_option_2);
-    register_float_method(class, "switch_3", set_option_3);
-    register_float_method(class, "switch_4", set_option_4);
-    register_float_method(class, "switch_5", set_option_5);
-    register_float_method(class, "switch_6", set_option_6);
-    register_float_method(class, "switch_7", set_option_7);
-    register_float_method(class, "switch_8", set_option_8);
-    register_float_method(class, "switch_9", set_option_9);
-    register_float_method(class, "switch_10", set_option_10);
-}
-
-unsafe fn create_class() -> *mut pd_sys::_class {
-    log::info("[kaseta~] initializing");
-
-    pd_sys::class_new(
-        pd_sys::gensym(cstr::cstr("kaseta~").as_ptr()),
-        Some(new),
-        None,
-        std::mem::size_of::<Class>(),
-        pd_sys::CLASS_DEFAULT as i32,
-        0,
-    )
-}
-
-unsafe extern "C" fn new() -> *mut c_void {
-    let class = pd_sys::pd_new(CLASS.unwrap()) as *mut Class;
-
-    let cache = Store::new();
-    let processor = {
-        let sample_rate = pd_sys::sys_getsr();
-        // TODO: Do I need to initialize processor with attributes?
-        Processor::new(sample_rate, &mut *MEMORY_MANAGER.lock().unwrap())
-    };
-
-    (*class).input = InputSnapshot::default();
-    (*class).control_connected = [false; 4];
-    (*class).cache = cache;
-    (*class).processor = processor;
-
-    pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).right_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).led_1_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).led_2_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).led_3_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).led_4_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).led_5_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).led_6_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).led_7_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).led_8_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).led_9_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-    (*class).impulse_outlet = pd_sys::outlet_new(&mut (*class).pd_obj, &mut pd_sys::s_signal);
-
-    class as *mut c_void
-}
+    wrapper::register_class::<Kaseta>();
+}
+
+impl PdClass for Kaseta {
+    const NAME: &'static str = "kaseta~";
+    const SIGNAL_INLETS: usize = 2;
+    const SIGNAL_OUTLETS: usize = 12;
+    const MULTICHANNEL: bool = true;
+
+    /// The processor works on buffers of 32 frames.
+    const GRANULARITY: usize = 32;
+
+    const PARAMETERS: &'static [Parameter<Self>] = &[
+        parameter!("bypass", Kaseta::set_bypass, Setting),
+        parameter!("mute", Kaseta::set_mute, Setting),
+        parameter!("seed", Kaseta::set_seed, Setting),
+        parameter!(
+            "control_1_connected",
+            Kaseta::set_control_1_connected,
+            Setting
+        ),
+        parameter!(
+            "control_2_connected",
+            Kaseta::set_control_2_connected,
+            Setting
+        ),
+        parameter!(
+            "control_3_connected",
+            Kaseta::set_control_3_connected,
+            Setting
+        ),
+        parameter!(
+            "control_4_connected",
+            Kaseta::set_control_4_connected,
+            Setting
+        ),
+        parameter!("control_1", Kaseta::set_control_1, Input, -5.0..=5.0),
+        parameter!("control_2", Kaseta::set_control_2, Input, -5.0..=5.0),
+        parameter!("control_3", Kaseta::set_control_3, Input, -5.0..=5.0),
+        parameter!("control_4", Kaseta::set_control_4, Input, -5.0..=5.0),
+        parameter!("button", Kaseta::set_button, Setting),
+        parameter!("pre_amp", Kaseta::set_pre_amp),
+        parameter!("dry_wet", Kaseta::set_dry_wet),
+        parameter!("drive", Kaseta::set_drive),
+        parameter!("bias", Kaseta::set_bias),
+        parameter!("wow_flutter", Kaseta::set_wow_flut),
+        parameter!("speed", Kaseta::set_speed),
+        parameter!("tone", Kaseta::set_tone),
+        parameter!("head_1_position", Kaseta::set_head_1_position),
+        parameter!("head_2_position", Kaseta::set_head_2_position),
+        parameter!("head_3_position", Kaseta::set_head_3_position),
+        parameter!("head_4_position", Kaseta::set_head_4_position),
+        parameter!("head_1_feedback", Kaseta::set_head_1_feedback),
+        parameter!("head_2_feedback", Kaseta::set_head_2_feedback),
+        parameter!("head_3_feedback", Kaseta::set_head_3_feedback),
+        parameter!("head_4_feedback", Kaseta::set_head_4_feedback),
+        parameter!("head_1_volume", Kaseta::set_head_1_volume),
+        parameter!("head_2_volume", Kaseta::set_head_2_volume),
+        parameter!("head_3_volume", Kaseta::set_head_3_volume),
+        parameter!("head_4_volume", Kaseta::set_head_4_volume),
+        parameter!("head_1_pan", Kaseta::set_head_1_pan),
+        parameter!("head_2_pan", Kaseta::set_head_2_pan),
+        parameter!("head_3_pan", Kaseta::set_head_3_pan),
+        parameter!("head_4_pan", Kaseta::set_head_4_pan),
+        parameter!("switch_1", Kaseta::set_option_1, Discrete),
+        parameter!("switch_2", Kaseta::set_option_2, Discrete),
+        parameter!("switch_3", Kaseta::set_option_3, Discrete),
+        parameter!("switch_4", Kaseta::set_option_4, Discrete),
+        parameter!("switch_5", Kaseta::set_option_5, Discrete),
+        parameter!("switch_6", Kaseta::set_option_6, Discrete),
+        parameter!("switch_7", Kaseta::set_option_7, Discrete),
+        parameter!("switch_8", Kaseta::set_option_8, Discrete),
+        parameter!("switch_9", Kaseta::set_option_9, Discrete),
+        parameter!("switch_10", Kaseta::set_option_10, Discrete),
+    ];
+
+    fn new(context: &mut Context) -> Result<Self, String> {
+        let cache = Store::new();
+        let (processor, memory) = {
+            let sample_rate = context.sample_rate();
+            let words = sample_rate as usize * TAPE_LENGTH;
+            // TODO: Do I need to initialize processor with attributes?
+            pool::allocate(words, |memory| Processor::new(sample_rate, memory))
+                .map_err(|shortage| shortage.to_string())?
+        };
+
+        Ok(Self {
+            input: InputSnapshot::default(),
+            control_connected: [false; 4],
+            output: DesiredOutput::default(),
+            cache,
+            processor,
+            _memory: memory,
+            random: KasetaRandom::from_entropy(),
+            wet: Fade::new(context.sample_rate(), true),
+            level: Fade::new(context.sample_rate(), true),
+        })
+    }
 
-unsafe fn register_bang_method(
-    class: *mut pd_sys::_class,
-    method: unsafe extern "C" fn(*mut Class),
-) {
-    pd_sys::class_addbang(
-        class,
-        Some(std::mem::transmute::<unsafe extern "C" fn(*mut Class), _>(
-            method,
-        )),
-    );
-}
+    fn register(class: &mut Class<Self>) {
+        class.add_bang_method(bang_method!(Kaseta::tick));
+    }
 
-unsafe fn register_float_method(
-    class: *mut pd_sys::_class,
-    symbol: &str,
-    method: unsafe extern "C" fn(*mut Class, pd_sys::t_float),
-) {
-    pd_sys::class_addmethod(
-        class,
-        Some(std::mem::transmute::<
-            unsafe extern "C" fn(*mut Class, pd_sys::t_float),
-            _,
-        >(method)),
-        pd_sys::gensym(cstr::cstr(symbol).as_ptr()),
-        pd_sys::t_atomtype::A_FLOAT,
-        0,
-    );
-}
+    // A stereo cable connected to the left inlet makes the left outlet stereo
+    // too. The right outlet keeps carrying the right channel alone.
+    fn signal_outlet_channels(&self, outlet: usize, inlet_channels: &[usize]) -> usize {
+        if outlet == 0 && inlet_channels[0] >= 2 {
+            2
+        } else {
+            1
+        }
+    }
 
-unsafe extern "C" fn tick(class: *mut Class) {
-    (*class).output = (*class).cache.tick();
+    fn perform(&mut self, number_of_frames: usize, inlets: &[&[f32]], outlets: &mut [&mut [f32]]) {
+        const BUFFER_LEN: usize = 32;
+        assert!(number_of_frames % BUFFER_LEN == 0);
+
+        let stereo_input = inlets[0].len() >= 2 * number_of_frames;
+        let stereo_output = outlets[0].len() >= 2 * number_of_frames;
+
+        let mut dry = [(0.0, 0.0); BUFFER_LEN];
+
+        for chunk_index in 0..number_of_frames / BUFFER_LEN {
+            for (i, frame) in dry.iter_mut().enumerate() {
+                let index = chunk_index * BUFFER_LEN + i;
+                *frame = if stereo_input {
+                    (inlets[0][index], inlets[0][number_of_frames + index])
+                } else {
+                    (inlets[0][index], inlets[1][index])
+                };
+            }
+
+            // The processor is not running at all while bypassed.
+            let mut buffer = dry;
+            if !self.wet.is_off() {
+                let reaction = self.processor.process(&mut buffer, &mut self.random);
+                self.cache.apply_dsp_reaction(reaction.into());
+                if !self.wet.is_on() {
+                    for (frame, dry) in buffer.iter_mut().zip(dry.iter()) {
+                        let wet = self.wet.next();
+                        frame.0 = frame.0 * wet + dry.0 * (1.0 - wet);
+                        frame.1 = frame.1 * wet + dry.1 * (1.0 - wet);
+                    }
+                }
+            }
+
+            if !self.level.is_on() {
+                for frame in buffer.iter_mut() {
+                    let level = self.level.next();
+                    frame.0 *= level;
+                    frame.1 *= level;
+                }
+            }
+
+            for (i, frame) in buffer.iter().enumerate() {
+                let index = chunk_index * BUFFER_LEN + i;
+                (outlets[0][index], outlets[1][index]) = *frame;
+                if stereo_output {
+                    outlets[0][number_of_frames + index] = frame.1;
+                }
+                outlets[2][index] = bool_to_f32(self.output.display[0]);
+                outlets[3][index] = bool_to_f32(self.output.display[1]);
+                outlets[4][index] = bool_to_f32(self.output.display[2]);
+                outlets[5][index] = bool_to_f32(self.output.display[3]);
+                outlets[6][index] = bool_to_f32(self.output.display[4]);
+                outlets[7][index] = bool_to_f32(self.output.display[5]);
+                outlets[8][index] = bool_to_f32(self.output.display[6]);
+                outlets[9][index] = bool_to_f32(self.output.display[7]);
+                outlets[10][index] = bool_to_f32(self.output.impulse_led);
+                outlets[11][index] = bool_to_f32(self.output.impulse_trigger);
+            }
+        }
+    }
 }
 
 macro_rules! set_control_connected {
     ( $name:ident, $index:expr ) => {
-        unsafe extern "C" fn $name(class: *mut Class, value: f32) {
+        fn $name(&mut self, value: f32) {
             let connected = value > 0.5;
-            (*class).control_connected[$index] = connected;
+            self.control_connected[$index] = connected;
         }
     };
 }
 
-set_control_connected!(set_control_1_connected, 0);
-set_control_connected!(set_control_2_connected, 1);
-set_control_connected!(set_control_3_connected, 2);
-set_control_connected!(set_control_4_connected, 3);
-
 macro_rules! set_control {
     ( $name:ident, $index:expr ) => {
-        unsafe extern "C" fn $name(class: *mut Class, value: f32) {
-            (*class).input.control[$index] = if (*class).control_connected[$index] {
+        fn $name(&mut self, value: f32) {
+            self.input.control[$index] = if self.control_connected[$index] {
                 Some(value)
             } else {
                 None
             };
-            update_processor(class);
+            self.update_processor();
         }
     };
 }
 
-set_control!(set_control_1, 0);
-set_control!(set_control_2, 1);
-set_control!(set_control_3, 2);
-set_control!(set_control_4, 3);
-
-unsafe extern "C" fn set_button(class: *mut Class, value: f32) {
-    let enabled = value > 0.5;
-    (*class).input.button = enabled;
-    update_processor(class);
+macro_rules! set_option {
+    ( $name:ident, $index:expr ) => {
+        fn $name(&mut self, enabled: f32) {
+            let enabled = enabled > 0.5;
+            self.input.switch[$index] = enabled;
+            self.update_processor();
+        }
+    };
 }
 
-unsafe extern "C" fn set_pre_amp(class: *mut Class, value: f32) {
-    (*class).input.pre_amp = value;
-    update_processor(class);
-}
+impl Kaseta {
+    pub(crate) fn tick(&mut self) {
+        self.output = self.cache.tick();
+    }
 
-unsafe extern "C" fn set_dry_wet(class: *mut Class, value: f32) {
-    (*class).input.dry_wet = value;
-    update_processor(class);
-}
+    // Crossfades to the dry input and stops processing.
+    fn set_bypass(&mut self, value: f32) {
+        self.wet.set(value < 0.5);
+    }
 
-unsafe extern "C" fn set_drive(class: *mut Class, value: f32) {
-    (*class).input.drive = value;
-    update_processor(class);
-}
+    // Fades the output out, while the tape keeps running.
+    fn set_mute(&mut self, value: f32) {
+        self.level.set(value < 0.5);
+    }
 
-unsafe extern "C" fn set_bias(class: *mut Class, value: f32) {
-    (*class).input.bias = value;
-    update_processor(class);
-}
+    // Makes wow, flutter and other random modulation reproducible.
+    fn set_seed(&mut self, value: f32) {
+        self.random = KasetaRandom::from_seed(value as u64);
+    }
 
-unsafe extern "C" fn set_wow_flut(class: *mut Class, value: f32) {
-    (*class).input.wow_flut = value;
-    update_processor(class);
-}
+    set_control_connected!(set_control_1_connected, 0);
+    set_control_connected!(set_control_2_connected, 1);
+    set_control_connected!(set_control_3_connected, 2);
+    set_control_connected!(set_control_4_connected, 3);
 
-unsafe extern "C" fn set_speed(class: *mut Class, value: f32) {
-    (*class).input.speed = value;
-    update_processor(class);
-}
+    set_control!(set_control_1, 0);
+    set_control!(set_control_2, 1);
+    set_control!(set_control_3, 2);
+    set_control!(set_control_4, 3);
 
-unsafe extern "C" fn set_tone(class: *mut Class, value: f32) {
-    (*class).input.tone = value;
-    update_processor(class);
-}
+    fn set_button(&mut self, value: f32) {
+        let enabled = value > 0.5;
+        self.input.button = enabled;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_1_position(class: *mut Class, value: f32) {
-    (*class).input.head[0].position = value;
-    update_processor(class);
-}
+    fn set_pre_amp(&mut self, value: f32) {
+        self.input.pre_amp = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_2_position(class: *mut Class, value: f32) {
-    (*class).input.head[1].position = value;
-    update_processor(class);
-}
+    fn set_dry_wet(&mut self, value: f32) {
+        self.input.dry_wet = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_3_position(class: *mut Class, value: f32) {
-    (*class).input.head[2].position = value;
-    update_processor(class);
-}
+    fn set_drive(&mut self, value: f32) {
+        self.input.drive = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_4_position(class: *mut Class, value: f32) {
-    (*class).input.head[3].position = value;
-    update_processor(class);
-}
+    fn set_bias(&mut self, value: f32) {
+        self.input.bias = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_1_volume(class: *mut Class, value: f32) {
-    (*class).input.head[0].volume = value;
-    update_processor(class);
-}
+    fn set_wow_flut(&mut self, value: f32) {
+        self.input.wow_flut = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_2_volume(class: *mut Class, value: f32) {
-    (*class).input.head[1].volume = value;
-    update_processor(class);
-}
+    fn set_speed(&mut self, value: f32) {
+        self.input.speed = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_3_volume(class: *mut Class, value: f32) {
-    (*class).input.head[2].volume = value;
-    update_processor(class);
-}
+    fn set_tone(&mut self, value: f32) {
+        self.input.tone = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_4_volume(class: *mut Class, value: f32) {
-    (*class).input.head[3].volume = value;
-    update_processor(class);
-}
+    fn set_head_1_position(&mut self, value: f32) {
+        self.input.head[0].position = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_1_feedback(class: *mut Class, value: f32) {
-    (*class).input.head[0].feedback = value;
-    update_processor(class);
-}
+    fn set_head_2_position(&mut self, value: f32) {
+        self.input.head[1].position = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_2_feedback(class: *mut Class, value: f32) {
-    (*class).input.head[1].feedback = value;
-    update_processor(class);
-}
+    fn set_head_3_position(&mut self, value: f32) {
+        self.input.head[2].position = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_3_feedback(class: *mut Class, value: f32) {
-    (*class).input.head[2].feedback = value;
-    update_processor(class);
-}
+    fn set_head_4_position(&mut self, value: f32) {
+        self.input.head[3].position = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_4_feedback(class: *mut Class, value: f32) {
-    (*class).input.head[3].feedback = value;
-    update_processor(class);
-}
+    fn set_head_1_volume(&mut self, value: f32) {
+        self.input.head[0].volume = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_1_pan(class: *mut Class, value: f32) {
-    (*class).input.head[0].pan = value;
-    update_processor(class);
-}
+    fn set_head_2_volume(&mut self, value: f32) {
+        self.input.head[1].volume = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_2_pan(class: *mut Class, value: f32) {
-    (*class).input.head[1].pan = value;
-    update_processor(class);
-}
+    fn set_head_3_volume(&mut self, value: f32) {
+        self.input.head[2].volume = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_3_pan(class: *mut Class, value: f32) {
-    (*class).input.head[2].pan = value;
-    update_processor(class);
-}
+    fn set_head_4_volume(&mut self, value: f32) {
+        self.input.head[3].volume = value;
+        self.update_processor();
+    }
 
-unsafe extern "C" fn set_head_4_pan(class: *mut Class, value: f32) {
-    (*class).input.head[3].pan = value;
-    update_processor(class);
-}
+    fn set_head_1_feedback(&mut self, value: f32) {
+        self.input.head[0].feedback = value;
+        self.update_processor();
+    }
 
-macro_rules! set_option {
-    ( $name:ident, $index:expr ) => {
-        unsafe extern "C" fn $name(class: *mut Class, enabled: f32) {
-            let enabled = enabled > 0.5;
-            (*class).input.switch[$index] = enabled;
-            update_processor(class);
-        }
-    };
-}
+    fn set_head_2_feedback(&mut self, value: f32) {
+        self.input.head[1].feedback = value;
+        self.update_processor();
+    }
 
-set_option!(set_option_1, 0);
-set_option!(set_option_2, 1);
-set_option!(set_option_3, 2);
-set_option!(set_option_4, 3);
-set_option!(set_option_5, 4);
-set_option!(set_option_6, 5);
-set_option!(set_option_7, 6);
-set_option!(set_option_8, 7);
-set_option!(set_option_9, 8);
-set_option!(set_option_10, 9);
-
-unsafe fn update_processor(class: *mut Class) {
-    let attributes = (*class)
-        .cache
-        .apply_input_snapshot((*class).input)
-        .dsp_attributes;
-    (*class).processor.set_attributes(attributes.into());
+    fn set_head_3_feedback(&mut self, value: f32) {
+        self.input.head[2].feedback = value;
+        self.update_processor();
+    }
+
+    fn set_head_4_feedback(&mut self, value: f32) {
+        self.input.head[3].feedback = value;
+        self.update_processor();
+    }
+
+    fn set_head_1_pan(&mut self, value: f32) {
+        self.input.head[0].pan = value;
+        self.update_processor();
+    }
+
+    fn set_head_2_pan(&mut self, value: f32) {
+        self.input.head[1].pan = value;
+        self.update_processor();
+    }
+
+    fn set_head_3_pan(&mut self, value: f32) {
+        self.input.head[2].pan = value;
+        self.update_processor();
+    }
+
+    fn set_head_4_pan(&mut self, value: f32) {
+        self.input.head[3].pan = value;
+        self.update_processor();
+    }
+
+    set_option!(set_option_1, 0);
+    set_option!(set_option_2, 1);
+    set_option!(set_option_3, 2);
+    set_option!(set_option_4, 3);
+    set_option!(set_option_5, 4);
+    set_option!(set_option_6, 5);
+    set_option!(set_option_7, 6);
+    set_option!(set_option_8, 7);
+    set_option!(set_option_9, 8);
+    set_option!(set_option_10, 9);
+
+    fn update_processor(&mut self) {
+        let attributes = self.cache.apply_input_snapshot(self.input).dsp_attributes;
+        self.processor.set_attributes(attributes.into());
+    }
 }
 
 fn bool_to_f32(x: bool) -> f32 {
@@ -380,40 +432,3 @@ fn bool_to_f32(x: bool) -> f32 {
         0.0
     }
 }
-
-fn perform(
-    class: &mut Class,
-    number_of_frames: usize,
-    inlets: &[&mut [pd_sys::t_float]],
-    outlets: &mut [&mut [pd_sys::t_float]],
-) {
-    const BUFFER_LEN: usize = 32;
-    assert!(number_of_frames % BUFFER_LEN == 0);
-
-    let mut buffer = [(0.0, 0.0); BUFFER_LEN];
-
-    for chunk_index in 0..number_of_frames / BUFFER_LEN {
-        for (i, frame) in buffer.iter_mut().enumerate() {
-            let index = chunk_index * BUFFER_LEN + i;
-            *frame = (inlets[0][index], 0.0);
-        }
-
-        let reaction = class.processor.process(&mut buffer, &mut KasetaRandom);
-        class.cache.apply_dsp_reaction(reaction.into());
-
-        for (i, frame) in buffer.iter().enumerate() {
-            let index = chunk_index * BUFFER_LEN + i;
-            (outlets[0][index], outlets[1][index]) = *frame;
-            outlets[2][index] = bool_to_f32(class.output.display[0]);
-            outlets[3][index] = bool_to_f32(class.output.display[1]);
-            outlets[4][index] = bool_to_f32(class.output.display[2]);
-            outlets[5][index] = bool_to_f32(class.output.display[3]);
-            outlets[6][index] = bool_to_f32(class.output.display[4]);
-            outlets[7][index] = bool_to_f32(class.output.display[5]);
-            outlets[8][index] = bool_to_f32(class.output.display[6]);
-            outlets[9][index] = bool_to_f32(class.output.display[7]);
-            outlets[10][index] = bool_to_f32(class.output.impulse_led);
-            outlets[11][index] = bool_to_f32(class.output.impulse_trigger);
-        }
-    }
-}
@@ -1,2 +1,7 @@
 pub mod achordion;
 pub mod kaseta;
+
+mod fade;
+mod harmony;
+mod scala;
+mod scale;
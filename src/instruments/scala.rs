@@ -0,0 +1,221 @@
+//! Microtonal tunings in the format of the Scala program.
+//!
+//! A `.scl` file lists pitches of a scale in cents or as ratios, the last of
+//! them being the period the scale repeats after. An optional `.kbm` file
+//! picks which of its steps are played and what interval is the formal
+//! octave. Pitch is given by V/Oct and the scale root, so the reference note
+//! and frequency of the keyboard mapping are ignored.
+//!
+//! See <https://www.huygens-fokker.org/scala/scl_format.html>.
+
+use super::scale::Scale;
+
+/// Lines of the file with comments skipped. Blank lines are kept, since the
+/// description of a scale may be empty.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('!'))
+}
+
+/// Pitch in octaves, given either in cents when it contains a period, or as
+/// a ratio of integers.
+fn parse_pitch(text: &str) -> Result<f32, String> {
+    let invalid = || format!("invalid pitch {}", text);
+    let word = text.split_whitespace().next().ok_or_else(invalid)?;
+    if word.contains('.') {
+        let cents: f32 = word.parse().map_err(|_| invalid())?;
+        return Ok(cents / 1200.0);
+    }
+    let (numerator, denominator) = word.split_once('/').unwrap_or((word, "1"));
+    let numerator: f64 = numerator.parse().map_err(|_| invalid())?;
+    let denominator: f64 = denominator.parse().map_err(|_| invalid())?;
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return Err(invalid());
+    }
+    Ok((numerator / denominator).log2() as f32)
+}
+
+/// Pitches of a `.scl` file, the first step 1/1 being implicit.
+pub fn parse_scale(text: &str) -> Result<Vec<f32>, String> {
+    let mut lines = lines(text).skip(1); // Description.
+    let count: usize = lines
+        .next()
+        .and_then(|line| line.split_whitespace().next()?.parse().ok())
+        .ok_or("missing number of notes")?;
+    let pitches = lines
+        .filter(|line| !line.is_empty())
+        .take(count)
+        .map(parse_pitch)
+        .collect::<Result<Vec<_>, _>>()?;
+    if pitches.len() != count {
+        return Err(format!("expected {} notes, found {}", count, pitches.len()));
+    }
+    if count == 0 {
+        return Err("scale has no notes".to_string());
+    }
+    Ok(pitches)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyboard {
+    /// Degree of each key of the repeating pattern, `None` for unmapped ones.
+    mapping: Vec<Option<i32>>,
+    /// Degree of the scale the pattern repeats after.
+    octave_degree: i32,
+}
+
+pub fn parse_keyboard(text: &str) -> Result<Keyboard, String> {
+    let mut lines = lines(text).filter(|line| !line.is_empty());
+    let mut header = |what: &str| -> Result<f64, String> {
+        lines
+            .next()
+            .and_then(|line| line.split_whitespace().next()?.parse().ok())
+            .ok_or_else(|| format!("missing {}", what))
+    };
+    let size = header("size of map")? as usize;
+    for what in [
+        "first note",
+        "last note",
+        "middle note",
+        "reference note",
+        "reference frequency",
+    ] {
+        header(what)?;
+    }
+    let octave_degree = header("formal octave degree")? as i32;
+
+    let mut mapping: Vec<Option<i32>> = lines
+        .take(size)
+        .map(|line| line.split_whitespace().next()?.parse().ok())
+        .collect();
+    // Keys missing at the end of the file are unmapped.
+    mapping.resize(size, None);
+    Ok(Keyboard {
+        mapping,
+        octave_degree,
+    })
+}
+
+/// Build a scale out of `.scl` pitches, optionally mapped by a keyboard.
+pub fn tuning(pitches: &[f32], keyboard: Option<&Keyboard>) -> Result<Scale, String> {
+    let (last, steps) = pitches.split_last().ok_or("scale has no notes")?;
+    let steps: Vec<f32> = [0.0].iter().chain(steps).copied().collect();
+    let period = *last;
+
+    // An empty map is the linear one, each key playing the next degree.
+    let Some(keyboard) = keyboard.filter(|k| !k.mapping.is_empty()) else {
+        return Scale::from_octaves(&steps, period);
+    };
+
+    let pitch = |degree: i32| {
+        let len = steps.len() as i32;
+        degree.div_euclid(len) as f32 * period + steps[degree.rem_euclid(len) as usize]
+    };
+    let mapped: Vec<f32> = keyboard
+        .mapping
+        .iter()
+        .flatten()
+        .map(|d| pitch(*d))
+        .collect();
+    if mapped.is_empty() {
+        return Err("keyboard maps no notes".to_string());
+    }
+    Scale::from_octaves(&mapped, pitch(keyboard.octave_degree))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    fn edo19() -> String {
+        let mut text = "! 19edo.scl\n!\n19 equal divisions of the octave\n 19\n!\n".to_string();
+        for step in 1..=19 {
+            text.push_str(&format!(" {:.5}\n", step as f32 * 1200.0 / 19.0));
+            if step == 10 {
+                text.push_str("  ! Indented comments are skipped too.\n");
+            }
+        }
+        text
+    }
+
+    const JUST: &str = "! just.scl
+!
+Ptolemy's intense diatonic
+ 7
+!
+ 9/8
+ 5/4
+ 4/3
+ 3/2
+ 5/3
+ 15/8
+ 2
+";
+
+    #[test]
+    fn it_quantizes_to_nineteen_equal_divisions() {
+        let scale = tuning(&parse_scale(&edo19()).unwrap(), None).unwrap();
+        let step = 1.0 / 19.0;
+
+        assert_eq!(scale.number_of_steps(), 19);
+        assert_close(scale.quantize(1.0 + 0.6 * step, 0.0), 1.0 + step);
+        assert_close(scale.quantize(1.0 + 0.4 * step, 0.0), 1.0);
+        assert_close(scale.quantize(0.49, 0.0), 9.0 * step);
+        assert_close(scale.pitch(-19, 0.0), -1.0);
+    }
+
+    #[test]
+    fn it_quantizes_to_just_intonation() {
+        let scale = tuning(&parse_scale(JUST).unwrap(), None).unwrap();
+        let fifth = 1.5_f32.log2();
+        let third = 1.25_f32.log2();
+
+        // Tempered fifth of 700 cents snaps to the pure one of 702 cents.
+        assert_close(scale.quantize(2.0 + 7.0 / 12.0, 0.0), 2.0 + fifth);
+        assert_close(scale.quantize(4.0 / 12.0, 0.0), third);
+        assert_close(scale.pitch(2, 0.5), 0.5 + third);
+    }
+
+    #[test]
+    fn it_maps_steps_through_keyboard() {
+        // Only the root, third and fifth of the just scale are played.
+        let kbm = "! triad.kbm
+12
+0
+127
+60
+69
+440.0
+7
+0
+x
+x
+x
+2
+x
+x
+4
+x
+";
+        let keyboard = parse_keyboard(kbm).unwrap();
+        assert_eq!(keyboard.mapping.len(), 12);
+
+        let scale = tuning(&parse_scale(JUST).unwrap(), Some(&keyboard)).unwrap();
+        assert_eq!(scale.number_of_steps(), 3);
+        assert_close(scale.quantize(0.5, 0.0), 1.5_f32.log2());
+        assert_close(scale.quantize(0.9, 0.0), 1.0);
+    }
+
+    #[test]
+    fn it_rejects_malformed_files() {
+        assert!(parse_scale("empty\n").is_err());
+        assert!(parse_scale("short\n 3\n 100.0\n 2/1\n").is_err());
+        assert!(parse_scale("bad\n 1\n -3/2\n").is_err());
+        assert!(parse_keyboard("12\n0\n").is_err());
+    }
+}
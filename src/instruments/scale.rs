@@ -0,0 +1,168 @@
+//! Scales defined by the user, shared by all objects of a Pure Data
+//! instance.
+//!
+//! A scale is a list of semitones within an octave, starting from its root,
+//! e.g. `0 2 3 7 8` of the hirajoshi, or a microtonal tuning loaded from a
+//! Scala file. Defining a scale under a name already in use replaces it,
+//! objects that selected it before keep using the previous definition until
+//! they select it again.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::instance;
+
+const SEMITONES_IN_OCTAVE: f32 = 12.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    /// Sorted pitches of steps within the period, in octaves.
+    steps: Vec<f32>,
+    /// Interval the steps repeat after, in octaves. It does not have to be
+    /// an octave, e.g. the tritave of Bohlen-Pierce.
+    period: f32,
+}
+
+impl Scale {
+    /// Steps are taken modulo the octave, so `12` is the same as `0`, and
+    /// the root is always included.
+    pub fn from_semitones(semitones: &[f32]) -> Result<Self, String> {
+        if semitones.iter().any(|s| !s.is_finite()) {
+            return Err("steps of a scale must be numbers".to_string());
+        }
+        let steps: Vec<f32> = semitones
+            .iter()
+            .map(|s| s / SEMITONES_IN_OCTAVE)
+            .chain([0.0])
+            .collect();
+        Self::from_octaves(&steps, 1.0)
+    }
+
+    /// Pitches of steps are given in octaves, taken modulo the period.
+    pub fn from_octaves(steps: &[f32], period: f32) -> Result<Self, String> {
+        if !(period.is_finite() && period > 0.0) {
+            return Err("period of a scale must be above zero".to_string());
+        }
+        if steps.is_empty() || steps.iter().any(|s| !s.is_finite()) {
+            return Err("scale must have at least one step".to_string());
+        }
+        let mut steps: Vec<f32> = steps.iter().map(|s| s.rem_euclid(period)).collect();
+        steps.sort_by(f32::total_cmp);
+        steps.dedup_by(|a, b| (*a - *b).abs() < 1e-5);
+        Ok(Self { steps, period })
+    }
+
+    pub fn number_of_steps(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Snap V/Oct to the closest step of the scale, placed on the given root.
+    /// Only the position of the root within the period matters.
+    pub fn quantize(&self, voct: f32, root: f32) -> f32 {
+        self.pitch(self.degree(voct, root), root)
+    }
+
+    /// Index of the closest step counting from the root of the period
+    /// containing 0 V. Degrees of lower periods are negative.
+    pub fn degree(&self, voct: f32, root: f32) -> i32 {
+        let position = voct - root.rem_euclid(self.period);
+        let len = self.number_of_steps() as i32;
+        let period = (position / self.period).floor();
+        let within = position - period * self.period;
+
+        // Steps are sorted, the closest one is either the first step above
+        // the position, or the one before it.
+        let above = period as i32 * len + self.steps.partition_point(|s| *s < within) as i32;
+        let distance = |degree| (self.offset(degree) - position).abs();
+        if distance(above - 1) <= distance(above) {
+            above - 1
+        } else {
+            above
+        }
+    }
+
+    /// V/Oct of the degree, inverse of `degree`.
+    pub fn pitch(&self, degree: i32, root: f32) -> f32 {
+        root.rem_euclid(self.period) + self.offset(degree)
+    }
+
+    /// Distance of the degree from the root of the period containing 0 V.
+    fn offset(&self, degree: i32) -> f32 {
+        let len = self.number_of_steps() as i32;
+        let period = degree.div_euclid(len) as f32 * self.period;
+        period + self.steps[degree.rem_euclid(len) as usize]
+    }
+}
+
+lazy_static! {
+    static ref SCALES: Mutex<HashMap<(usize, String), Arc<Scale>>> = Mutex::new(HashMap::new());
+}
+
+pub fn define(name: &str, scale: impl Into<Arc<Scale>>) {
+    SCALES
+        .lock()
+        .unwrap()
+        .insert((instance::current(), name.to_string()), scale.into());
+}
+
+pub fn find(name: &str) -> Option<Arc<Scale>> {
+    SCALES
+        .lock()
+        .unwrap()
+        .get(&(instance::current(), name.to_string()))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn it_quantizes_to_closest_step_of_the_scale() {
+        let hirajoshi = Scale::from_semitones(&[0.0, 2.0, 3.0, 7.0, 8.0]).unwrap();
+        let semitone = 1.0 / 12.0;
+
+        assert_close(
+            hirajoshi.quantize(1.0 + 1.1 * semitone, 0.0),
+            1.0 + 2.0 * semitone,
+        );
+        assert_close(
+            hirajoshi.quantize(1.0 + 5.4 * semitone, 0.0),
+            1.0 + 7.0 * semitone,
+        );
+        assert_close(hirajoshi.quantize(1.0 + 10.5 * semitone, 0.0), 2.0);
+        assert_close(
+            hirajoshi.quantize(2.5 * semitone, 3.0 + semitone),
+            3.0 * semitone,
+        );
+        assert_close(hirajoshi.quantize(-0.1 * semitone, 0.0), 0.0);
+
+        assert_eq!(hirajoshi.degree(1.0, 0.0), 5);
+        assert_eq!(hirajoshi.degree(-semitone * 4.0, 0.0), -1);
+        assert_close(hirajoshi.pitch(-1, 0.0), -4.0 * semitone);
+    }
+
+    #[test]
+    fn it_normalizes_steps() {
+        let scale = Scale::from_semitones(&[7.0, 12.0, 4.0, 4.0, -1.0]).unwrap();
+        assert_eq!(scale.number_of_steps(), 4);
+        for (step, semitones) in scale.steps.iter().zip([0.0, 4.0, 7.0, 11.0]) {
+            assert_close(*step, semitones / 12.0);
+        }
+        assert!(Scale::from_semitones(&[f32::NAN]).is_err());
+    }
+
+    #[test]
+    fn it_repeats_steps_after_a_period_other_than_octave() {
+        let tritave = 3.0_f32.log2();
+        let scale = Scale::from_octaves(&[0.0, tritave / 2.0], tritave).unwrap();
+
+        assert_close(scale.quantize(1.0, 0.0), tritave / 2.0);
+        assert_close(scale.quantize(1.4, 0.0), tritave);
+        assert_close(scale.pitch(4, 0.1), 0.1 + 2.0 * tritave);
+    }
+}
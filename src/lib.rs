@@ -11,47 +11,40 @@ extern crate lazy_static;
 mod wrapper;
 
 pub mod instruments;
+pub mod render;
 
+mod automation;
+mod choice;
+mod clock;
+mod controls;
 mod cstr;
+mod hub;
+mod instance;
 mod log;
+mod meter;
+mod midi;
+mod osc;
+mod pool;
+mod preset;
 
-use std::os::raw::c_void;
-
+use hub::Automaton;
 use instruments::achordion;
 use instruments::kaseta;
 
-static mut AUTOMATON_CLASS: Option<*mut pd_sys::_class> = None;
-
-#[repr(C)]
-struct Automaton {
-    _pd_obj: pd_sys::t_object,
-}
-
-unsafe extern "C" fn automaton_new() -> *mut c_void {
-    let counter = pd_sys::pd_new(AUTOMATON_CLASS.unwrap()) as *mut Automaton;
-
-    counter as *mut c_void
-}
-
 #[no_mangle]
 pub unsafe extern "C" fn automaton_setup() {
-    log::info("[automaton] initializing");
+    log::set_sink(log::pd_sink);
 
-    let class = create_class();
+    // Pure Data instances embedded through libpd may each load the library,
+    // but classes and resources must not be set up twice for one instance.
+    if wrapper::is_registered::<Automaton>() {
+        log::warn("[automaton] already initialized");
+        return;
+    }
 
-    AUTOMATON_CLASS = Some(class);
+    wrapper::register_class::<Automaton>();
 
     achordion::achordion_tilde_setup();
+    achordion::achordion_quantizer_tilde_setup();
     kaseta::kaseta_tilde_setup();
 }
-
-unsafe fn create_class() -> *mut pd_sys::_class {
-    pd_sys::class_new(
-        pd_sys::gensym(cstr::cstr("automaton").as_ptr()),
-        Some(automaton_new),
-        None,
-        std::mem::size_of::<Automaton>(),
-        pd_sys::CLASS_NOINLET as i32,
-        0,
-    )
-}
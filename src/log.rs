@@ -1,8 +1,98 @@
+use std::os::raw::{c_int, c_void};
+use std::sync::OnceLock;
+
 use crate::cstr;
 
-pub fn info(message: &str) {
-    let m = cstr::cstr(message);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+type Sink = fn(Level, *const c_void, &str);
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+/// Messages are printed to the standard error output until a sink is set.
+/// That is the case of the offline renderer, which runs without Pure Data.
+pub fn set_sink(sink: Sink) {
+    let _ = SINK.set(sink);
+}
+
+/// Sink writing into the console of Pure Data. Errors of objects are posted
+/// through `pd_error`, so the object can be found by clicking on them.
+pub fn pd_sink(level: Level, object: *const c_void, message: &str) {
+    // Log levels of Pure Data's console.
+    const PD_ERROR: c_int = 1;
+    const PD_NORMAL: c_int = 2;
+    const PD_DEBUG: c_int = 3;
+
+    let format = cstr::cstr("%s");
+    let m = match level {
+        Level::Warn => cstr::cstr(&format!("warning: {}", message)),
+        _ => cstr::cstr(message),
+    };
     unsafe {
-        pd_sys::post(m.as_ptr());
+        match level {
+            Level::Error if !object.is_null() => {
+                pd_sys::pd_error(object, format.as_ptr(), m.as_ptr());
+            }
+            Level::Error => pd_sys::logpost(object, PD_ERROR, format.as_ptr(), m.as_ptr()),
+            Level::Warn | Level::Info => {
+                pd_sys::logpost(object, PD_NORMAL, format.as_ptr(), m.as_ptr())
+            }
+            Level::Debug => pd_sys::logpost(object, PD_DEBUG, format.as_ptr(), m.as_ptr()),
+        }
+    }
+}
+
+fn write(level: Level, object: *const c_void, message: &str) {
+    match SINK.get() {
+        Some(sink) => sink(level, object, message),
+        None => match level {
+            Level::Error => eprintln!("error: {}", message),
+            Level::Warn => eprintln!("warning: {}", message),
+            Level::Info => eprintln!("{}", message),
+            Level::Debug => eprintln!("debug: {}", message),
+        },
+    }
+}
+
+pub fn warn(message: &str) {
+    write(Level::Warn, std::ptr::null(), message);
+}
+
+pub fn debug(message: &str) {
+    write(Level::Debug, std::ptr::null(), message);
+}
+
+/// Logs on behalf of a single object.
+#[derive(Clone, Copy)]
+pub struct Logger {
+    object: *const c_void,
+}
+
+impl Logger {
+    /// Objects living outside of Pure Data pass a null pointer.
+    pub fn new(object: *const c_void) -> Self {
+        Self { object }
+    }
+
+    pub fn error(&self, message: &str) {
+        write(Level::Error, self.object, message);
+    }
+
+    pub fn warn(&self, message: &str) {
+        write(Level::Warn, self.object, message);
+    }
+
+    pub fn info(&self, message: &str) {
+        write(Level::Info, self.object, message);
+    }
+
+    pub fn debug(&self, message: &str) {
+        write(Level::Debug, self.object, message);
     }
 }
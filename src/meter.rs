@@ -0,0 +1,79 @@
+//! Measuring of DSP load of single objects.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Time constant of the average load, in seconds.
+const AVERAGE_WINDOW: f32 = 1.0;
+
+/// Share of the time available to process a block spent by an object. The
+/// meter is written by the audio thread and read by any other.
+#[derive(Default)]
+pub struct Meter {
+    average: AtomicU32,
+    peak: AtomicU32,
+}
+
+impl Meter {
+    /// Record processing of a block that took `elapsed` out of `deadline`.
+    pub fn record(&self, elapsed: Duration, deadline: Duration) {
+        if deadline.is_zero() {
+            return;
+        }
+        let load = elapsed.as_secs_f32() / deadline.as_secs_f32();
+
+        let smoothing = (deadline.as_secs_f32() / AVERAGE_WINDOW).min(1.0);
+        let average = load_f32(&self.average);
+        store_f32(&self.average, average + (load - average) * smoothing);
+
+        if load > load_f32(&self.peak) {
+            store_f32(&self.peak, load);
+        }
+    }
+
+    /// Start measuring from scratch, e.g. when the DSP graph changes.
+    pub fn reset(&self) {
+        store_f32(&self.average, 0.0);
+        store_f32(&self.peak, 0.0);
+    }
+
+    /// Average load in percent.
+    pub fn average(&self) -> f32 {
+        load_f32(&self.average) * 100.0
+    }
+
+    /// Highest load of a single block since the last reset, in percent.
+    pub fn peak(&self) -> f32 {
+        load_f32(&self.peak) * 100.0
+    }
+}
+
+fn load_f32(atomic: &AtomicU32) -> f32 {
+    f32::from_bits(atomic.load(Ordering::Relaxed))
+}
+
+fn store_f32(atomic: &AtomicU32, value: f32) {
+    atomic.store(value.to_bits(), Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_follows_average_and_keeps_peak_until_reset() {
+        let meter = Meter::default();
+        let deadline = Duration::from_millis(10);
+
+        meter.record(Duration::from_millis(5), deadline);
+        for _ in 0..1000 {
+            meter.record(Duration::from_millis(1), deadline);
+        }
+        assert!((meter.average() - 10.0).abs() < 0.1);
+        assert!((meter.peak() - 50.0).abs() < 0.1);
+
+        meter.reset();
+        assert_eq!(meter.average(), 0.0);
+        assert_eq!(meter.peak(), 0.0);
+    }
+}
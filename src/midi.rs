@@ -0,0 +1,285 @@
+//! Binding of MIDI control changes to parameters of objects.
+//!
+//! After `learn <parameter>`, the next control change received by the object
+//! gets bound to the parameter. Control changes come either through the `cc`
+//! message, taking the outlets of `[ctlin]` in order, or straight from Pure
+//! Data's `#ctlin` symbol, subscribed to once learning starts or a mapping
+//! gets restored.
+//!
+//! Mappings are saved with the patch as `#A map <parameter> <cc> <channel>`
+//! lines following the object, the same way arrays keep their content. The
+//! object only listens to `#A` while the patch it is created in loads.
+
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_void};
+use std::sync::Mutex;
+
+use crate::wrapper::{self, Atom, ClassPointer, Parameter};
+use crate::{cstr, instance};
+
+/// Highest value of a control change.
+const MAX_VALUE: f32 = 127.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub parameter: usize,
+    pub controller: u32,
+    pub channel: u32,
+}
+
+#[derive(Default)]
+pub struct MidiMap {
+    learning: Option<usize>,
+    mappings: Vec<Mapping>,
+}
+
+impl MidiMap {
+    /// Bind the next received control change to the parameter.
+    pub fn learn(&mut self, parameter: usize) {
+        self.learning = Some(parameter);
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learning.is_some()
+    }
+
+    /// A parameter is controlled by a single control change at most, mapping
+    /// it again replaces its previous mapping.
+    pub fn map(&mut self, parameter: usize, controller: u32, channel: u32) {
+        self.forget(parameter);
+        self.mappings.push(Mapping {
+            parameter,
+            controller,
+            channel,
+        });
+    }
+
+    pub fn forget(&mut self, parameter: usize) {
+        self.mappings.retain(|m| m.parameter != parameter);
+        if self.learning == Some(parameter) {
+            self.learning = None;
+        }
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// Return parameters controlled by the control change, with its value
+    /// scaled into their range.
+    pub fn receive<T>(
+        &mut self,
+        controller: u32,
+        channel: u32,
+        value: f32,
+        parameters: &[Parameter<T>],
+    ) -> Vec<(usize, f32)> {
+        if let Some(parameter) = self.learning.take() {
+            self.map(parameter, controller, channel);
+        }
+
+        let value = value.clamp(0.0, MAX_VALUE) / MAX_VALUE;
+        self.mappings
+            .iter()
+            .filter(|m| m.controller == controller && m.channel == channel)
+            .map(|m| {
+                let range = &parameters[m.parameter].range;
+                (
+                    m.parameter,
+                    range.start() + (range.end() - range.start()) * value,
+                )
+            })
+            .collect()
+    }
+}
+
+type Callback = unsafe fn(*mut c_void, &[Atom]);
+
+/// Proxy receiving lists sent to `#ctlin` on behalf of its owner. Binding
+/// the object itself would turn lists sent to its inlet into control changes.
+#[repr(C)]
+struct Receiver {
+    pd: pd_sys::t_pd,
+    owner: *mut c_void,
+    callback: Callback,
+}
+
+lazy_static! {
+    static ref RECEIVER_CLASSES: Mutex<HashMap<usize, ClassPointer>> = Mutex::new(HashMap::new());
+}
+
+unsafe fn receiver_class() -> *mut pd_sys::_class {
+    let mut classes = RECEIVER_CLASSES.lock().unwrap();
+    classes
+        .entry(instance::current())
+        .or_insert_with(|| {
+            let class = pd_sys::class_new(
+                pd_sys::gensym(cstr::cstr("automaton_ctlin").as_ptr()),
+                None,
+                None,
+                std::mem::size_of::<Receiver>(),
+                pd_sys::CLASS_PD as c_int,
+                pd_sys::t_atomtype::A_NULL,
+                0,
+            );
+            pd_sys::class_addlist(
+                class,
+                Some(std::mem::transmute::<
+                    unsafe extern "C" fn(
+                        *mut Receiver,
+                        *mut pd_sys::t_symbol,
+                        c_int,
+                        *mut pd_sys::t_atom,
+                    ),
+                    unsafe extern "C" fn(),
+                >(receive_list)),
+            );
+            ClassPointer(class)
+        })
+        .0
+}
+
+/// Lists of `#ctlin` carry the controller, value and channel.
+unsafe extern "C" fn receive_list(
+    receiver: *mut Receiver,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    let atoms = wrapper::from_pd_atoms(argc, argv);
+    if let [controller, value, channel] = atoms[..] {
+        ((*receiver).callback)((*receiver).owner, &[value, controller, channel]);
+    }
+}
+
+/// Connections of an object to Pure Data needed for MIDI learn. They are
+/// released when the object gets deleted.
+pub struct Bindings {
+    object: *mut pd_sys::t_pd,
+    receiver: *mut Receiver,
+    /// Fires once the patch the object was created in is loaded.
+    loaded: *mut pd_sys::t_clock,
+    /// Kept rather than called directly, so that controls owning the
+    /// bindings can be dropped by tests running without Pure Data.
+    release: unsafe fn(&mut Bindings),
+}
+
+impl Bindings {
+    /// Let the object receive `#A` messages following it in a patch file,
+    /// until the patch is loaded.
+    pub unsafe fn new(object: *mut pd_sys::t_pd) -> Self {
+        let symbol = restore_symbol();
+        // The way arrays and [text define] do, take over the symbol from
+        // whichever object was created before. Its messages have been
+        // already delivered.
+        (*symbol).s_thing = std::ptr::null_mut();
+        pd_sys::pd_bind(object, symbol);
+
+        // Patches are loaded within a single tick of the scheduler, the clock
+        // fires right after.
+        let loaded = pd_sys::clock_new(
+            object as *mut c_void,
+            Some(std::mem::transmute::<
+                unsafe extern "C" fn(*mut c_void),
+                unsafe extern "C" fn(),
+            >(release_restore)),
+        );
+        pd_sys::clock_delay(loaded, 0.0);
+
+        Self {
+            object,
+            receiver: std::ptr::null_mut(),
+            loaded,
+            release,
+        }
+    }
+
+    /// Start receiving control changes through `callback`, called with the
+    /// same arguments as the `cc` message.
+    pub unsafe fn subscribe(&mut self, callback: Callback) {
+        if !self.receiver.is_null() {
+            return;
+        }
+        let receiver = pd_sys::pd_new(receiver_class()) as *mut Receiver;
+        (*receiver).owner = self.object as *mut c_void;
+        (*receiver).callback = callback;
+        pd_sys::pd_bind(receiver as *mut pd_sys::t_pd, ctlin_symbol());
+        self.receiver = receiver;
+    }
+}
+
+impl Drop for Bindings {
+    fn drop(&mut self) {
+        unsafe { (self.release)(self) };
+    }
+}
+
+unsafe fn release(bindings: &mut Bindings) {
+    pd_sys::clock_free(bindings.loaded);
+    release_restore(bindings.object as *mut c_void);
+    if !bindings.receiver.is_null() {
+        pd_sys::pd_unbind(bindings.receiver as *mut pd_sys::t_pd, ctlin_symbol());
+        pd_sys::pd_free(bindings.receiver as *mut pd_sys::t_pd);
+    }
+}
+
+/// Stop receiving `#A` messages, unless another object took the symbol over
+/// already.
+unsafe extern "C" fn release_restore(object: *mut c_void) {
+    let symbol = restore_symbol();
+    if (*symbol).s_thing == object as *mut pd_sys::t_pd {
+        pd_sys::pd_unbind(object as *mut pd_sys::t_pd, symbol);
+    }
+}
+
+unsafe fn restore_symbol() -> *mut pd_sys::t_symbol {
+    pd_sys::gensym(cstr::cstr("#A").as_ptr())
+}
+
+unsafe fn ctlin_symbol() -> *mut pd_sys::t_symbol {
+    pd_sys::gensym(cstr::cstr("#ctlin").as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrapper::{FloatMethod, Kind, PdFloat};
+
+    unsafe extern "C" fn noop(_object: *mut c_void, _value: PdFloat) {}
+
+    fn parameters() -> Vec<Parameter<()>> {
+        let parameter = |name, range| Parameter {
+            name,
+            kind: Kind::Continuous,
+            range,
+            method: noop as FloatMethod,
+            set: |_, _| {},
+        };
+        vec![parameter("knob", 0.0..=1.0), parameter("cv", -5.0..=5.0)]
+    }
+
+    #[test]
+    fn it_binds_next_control_change_and_scales_it_into_range() {
+        let parameters = parameters();
+        let mut map = MidiMap::default();
+
+        assert!(map.receive(7, 1, 127.0, &parameters).is_empty());
+
+        map.learn(1);
+        assert_eq!(map.receive(7, 1, 127.0, &parameters), vec![(1, 5.0)]);
+        assert_eq!(map.receive(7, 1, 0.0, &parameters), vec![(1, -5.0)]);
+        assert!(map.receive(7, 2, 0.0, &parameters).is_empty());
+
+        map.learn(1);
+        map.receive(8, 1, 0.0, &parameters);
+        assert!(map.receive(7, 1, 0.0, &parameters).is_empty());
+        assert_eq!(
+            map.mappings(),
+            &[Mapping {
+                parameter: 1,
+                controller: 8,
+                channel: 1
+            }]
+        );
+    }
+}
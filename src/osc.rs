@@ -0,0 +1,387 @@
+//! Control of instrument parameters over OSC.
+//!
+//! A background thread listens on a UDP port, parses incoming packets and
+//! passes values to inboxes of the addressed objects. Objects empty their
+//! inbox at the start of every processed block, without taking any lock.
+//!
+//! An address names the module without the tilde, the number of the object
+//! counting from 1 in order of creation, and the parameter with its
+//! underscores replaced by slashes, e.g. `/kaseta/1/head/2/feedback`.
+
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::wrapper;
+
+/// Messages an object can receive between two processed blocks.
+const INBOX_CAPACITY: usize = 256;
+
+/// Only programs running on the same machine can reach the server, unless
+/// another address is given.
+pub const DEFAULT_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// How often the listening thread checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Parameter changes waiting for the audio thread. Any thread can push, only
+/// the object itself pops, and it never blocks doing so.
+pub struct Inbox {
+    slots: Box<[AtomicU64]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    producer: Mutex<()>,
+}
+
+impl Default for Inbox {
+    fn default() -> Self {
+        Self {
+            slots: (0..INBOX_CAPACITY).map(|_| AtomicU64::new(0)).collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            producer: Mutex::new(()),
+        }
+    }
+}
+
+impl Inbox {
+    /// Queue a value for the parameter of the given index. Returns false if
+    /// the inbox is full and the value got dropped.
+    pub fn push(&self, parameter: usize, value: f32) -> bool {
+        let _producer = self.producer.lock().unwrap();
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == INBOX_CAPACITY {
+            return false;
+        }
+        let entry = (parameter as u64) << 32 | u64::from(value.to_bits());
+        self.slots[tail % INBOX_CAPACITY].store(entry, Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    pub fn pop(&self) -> Option<(usize, f32)> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let entry = self.slots[head % INBOX_CAPACITY].load(Ordering::Relaxed);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(((entry >> 32) as usize, f32::from_bits(entry as u32)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub address: String,
+    pub arguments: Vec<f32>,
+}
+
+/// Parse a packet holding a message or a bundle of them. Arguments that are
+/// not numbers or booleans are skipped.
+pub fn parse(packet: &[u8]) -> Result<Vec<Message>, &'static str> {
+    let mut messages = Vec::new();
+    parse_into(packet, &mut messages)?;
+    Ok(messages)
+}
+
+fn parse_into(packet: &[u8], messages: &mut Vec<Message>) -> Result<(), &'static str> {
+    let mut reader = Reader {
+        packet,
+        position: 0,
+    };
+    let address = reader.string()?;
+
+    if address == "#bundle" {
+        reader.take(8)?; // Time tag, bundles are executed immediately.
+        while !reader.is_empty() {
+            let size = reader.int()?;
+            let size = usize::try_from(size).map_err(|_| "invalid size of bundle element")?;
+            parse_into(reader.take(size)?, messages)?;
+        }
+        return Ok(());
+    }
+
+    if !address.starts_with('/') {
+        return Err("address must start with a slash");
+    }
+    let tags = if reader.is_empty() {
+        ","
+    } else {
+        reader.string()?
+    };
+    let tags = tags
+        .strip_prefix(',')
+        .ok_or("type tags must start with a comma")?;
+
+    let mut arguments = Vec::new();
+    for tag in tags.chars() {
+        match tag {
+            'f' => arguments.push(f32::from_bits(reader.int()? as u32)),
+            'i' => arguments.push(reader.int()? as f32),
+            'd' => arguments.push(f64::from_bits(reader.long()? as u64) as f32),
+            'h' => {
+                reader.long()?;
+            }
+            'T' => arguments.push(1.0),
+            'F' => arguments.push(0.0),
+            's' | 'S' => {
+                reader.string()?;
+            }
+            'b' => {
+                let size = usize::try_from(reader.int()?).map_err(|_| "invalid blob")?;
+                reader.take(size.next_multiple_of(4))?;
+            }
+            'N' | 'I' => (),
+            _ => return Err("unsupported argument type"),
+        }
+    }
+
+    messages.push(Message {
+        address: address.to_string(),
+        arguments,
+    });
+    Ok(())
+}
+
+struct Reader<'a> {
+    packet: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.position >= self.packet.len()
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], &'static str> {
+        let end = self.position + size;
+        let bytes = self
+            .packet
+            .get(self.position..end)
+            .ok_or("packet is truncated")?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Strings are terminated by a null and padded to four bytes.
+    fn string(&mut self) -> Result<&'a str, &'static str> {
+        let rest = &self.packet[self.position.min(self.packet.len())..];
+        let length = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("string is not terminated")?;
+        let string = std::str::from_utf8(&rest[..length]).map_err(|_| "string is not UTF-8")?;
+        self.take((length + 1).next_multiple_of(4))?;
+        Ok(string)
+    }
+
+    fn int(&mut self) -> Result<i32, &'static str> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn long(&mut self) -> Result<i64, &'static str> {
+        let bytes = self.take(8)?;
+        Ok(i64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+/// Split an address into the name of the class, number of its object and
+/// name of the parameter.
+fn resolve(address: &str) -> Option<(String, usize, String)> {
+    let mut parts = address.strip_prefix('/')?.split('/');
+    let class = format!("{}~", parts.next()?);
+    let number = parts.next()?.parse().ok().filter(|n| *n > 0)?;
+    let parameter = parts.collect::<Vec<_>>().join("_");
+    if parameter.is_empty() {
+        return None;
+    }
+    Some((class, number, parameter))
+}
+
+#[derive(Default)]
+pub struct Statistics {
+    received: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+impl Statistics {
+    /// Messages delivered to an object.
+    pub fn received(&self) -> usize {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// Malformed messages, messages to unknown addresses and messages not
+    /// fitting into a full inbox.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Listening thread, stopped when dropped.
+pub struct Server {
+    port: u16,
+    running: Arc<AtomicBool>,
+    statistics: Arc<Statistics>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    /// Listen on the given address and port, port 0 picks any free one.
+    /// Messages are dispatched to objects of the given Pure Data instance.
+    pub fn start(address: IpAddr, port: u16, instance: usize) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind((address, port))?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let port = socket.local_addr()?.port();
+
+        let running = Arc::new(AtomicBool::new(true));
+        let statistics = Arc::new(Statistics::default());
+        let thread = {
+            let running = Arc::clone(&running);
+            let statistics = Arc::clone(&statistics);
+            thread::Builder::new()
+                .name("automaton-osc".to_string())
+                .spawn(move || listen(&socket, instance, &running, &statistics))?
+        };
+
+        Ok(Self {
+            port,
+            running,
+            statistics,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn listen(socket: &UdpSocket, instance: usize, running: &AtomicBool, statistics: &Statistics) {
+    let mut buffer = [0; 65536];
+    while running.load(Ordering::Relaxed) {
+        let Ok(size) = socket.recv(&mut buffer) else {
+            continue;
+        };
+        let Ok(messages) = parse(&buffer[..size]) else {
+            statistics.dropped.fetch_add(1, Ordering::Relaxed);
+            continue;
+        };
+        for message in messages {
+            let counter = if dispatch(&message, instance) {
+                &statistics.received
+            } else {
+                &statistics.dropped
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn dispatch(message: &Message, instance: usize) -> bool {
+    let (Some((class, number, parameter)), Some(value)) =
+        (resolve(&message.address), message.arguments.first())
+    else {
+        return false;
+    };
+    let object = wrapper::objects_of(instance)
+        .into_iter()
+        .filter(|o| o.name == class)
+        .nth(number - 1);
+    let inbox_and_index = object.and_then(|o| Some((o.inbox?, (o.find_parameter)(&parameter)?)));
+    match inbox_and_index {
+        Some((inbox, index)) => inbox.push(index, *value),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn padded(string: &str) -> Vec<u8> {
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.resize((string.len() + 1).next_multiple_of(4), 0);
+        bytes
+    }
+
+    fn message(address: &str, value: f32) -> Vec<u8> {
+        let mut packet = padded(address);
+        packet.extend(padded(",f"));
+        packet.extend(value.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn it_parses_messages_and_bundles() {
+        assert_eq!(
+            parse(&message("/kaseta/1/dry/wet", 0.5)),
+            Ok(vec![Message {
+                address: "/kaseta/1/dry/wet".to_string(),
+                arguments: vec![0.5],
+            }])
+        );
+
+        let mut bundle = padded("#bundle");
+        bundle.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        for value in [0.25, 0.75] {
+            let element = message("/achordion/2/detune", value);
+            bundle.extend((element.len() as i32).to_be_bytes());
+            bundle.extend(element);
+        }
+        let values: Vec<_> = parse(&bundle)
+            .unwrap()
+            .into_iter()
+            .flat_map(|m| m.arguments)
+            .collect();
+        assert_eq!(values, vec![0.25, 0.75]);
+
+        let mut integer = padded("/achordion/1/style");
+        integer.extend(padded(",i"));
+        integer.extend(3i32.to_be_bytes());
+        assert_eq!(parse(&integer).unwrap()[0].arguments, vec![3.0]);
+
+        assert!(parse(&message("/kaseta/1/tone", 0.5)[..10]).is_err());
+        assert!(parse(&padded("kaseta")).is_err());
+    }
+
+    #[test]
+    fn it_resolves_addresses() {
+        assert_eq!(
+            resolve("/kaseta/1/head/2/feedback"),
+            Some(("kaseta~".to_string(), 1, "head_2_feedback".to_string()))
+        );
+        assert_eq!(resolve("/kaseta/0/tone"), None);
+        assert_eq!(resolve("/kaseta/1"), None);
+    }
+
+    #[test]
+    fn inbox_keeps_order_and_drops_overflow() {
+        let inbox = Inbox::default();
+        for i in 0..INBOX_CAPACITY {
+            assert!(inbox.push(i, i as f32 / 2.0));
+        }
+        assert!(!inbox.push(0, 0.0));
+        for i in 0..INBOX_CAPACITY {
+            assert_eq!(inbox.pop(), Some((i, i as f32 / 2.0)));
+        }
+        assert_eq!(inbox.pop(), None);
+    }
+}
@@ -0,0 +1,227 @@
+//! Memory pool shared by all tape-based objects of all Pure Data instances.
+//!
+//! The pool is allocated when the first object asks for memory. Until then
+//! its size can be changed, e.g. to fit the library onto a board with little
+//! memory. Memory taken by an object is returned to the pool once the object
+//! gets deleted, to be reused by those created later. Objects that do not
+//! fit into what is left are refused.
+
+use core::mem::MaybeUninit;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use sirena::memory_manager::MemoryManager;
+
+use crate::instance;
+
+const WORD: usize = std::mem::size_of::<u32>();
+
+pub const MEGABYTE: f32 = 1024.0 * 1024.0;
+
+/// Enough for four kaseta~ objects running at 48 kHz.
+pub const DEFAULT_SIZE: usize = 48000 * 4 * 60 * 3 * WORD;
+
+struct Memory {
+    /// Address of the first word, kept as a number for the pool to be
+    /// shareable between threads.
+    start: usize,
+    /// Extents not taken by any object, as offset and length in words,
+    /// ordered by their offset.
+    free: Vec<(usize, usize)>,
+}
+
+struct Pool {
+    size: usize,
+    memory: Option<Memory>,
+    usage: HashMap<usize, usize>,
+}
+
+lazy_static! {
+    static ref POOL: Mutex<Pool> = Mutex::new(Pool {
+        size: DEFAULT_SIZE,
+        memory: None,
+        usage: HashMap::new(),
+    });
+}
+
+pub struct Usage {
+    /// Bytes taken by objects of the current Pure Data instance.
+    pub instance: usize,
+    /// Bytes taken by objects of all instances.
+    pub total: usize,
+    pub size: usize,
+}
+
+/// The object did not fit into any free extent of the pool.
+#[derive(Debug)]
+pub struct Shortage {
+    pub required: usize,
+    /// Bytes of the largest free extent.
+    pub available: usize,
+    pub size: usize,
+}
+
+impl fmt::Display for Shortage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} MB of tape memory needed, only {:.1} MB available in the pool of {:.1} MB, a larger one can be set with [automaton <megabytes>( before the first tape is created",
+            self.required as f32 / MEGABYTE,
+            self.available as f32 / MEGABYTE,
+            self.size as f32 / MEGABYTE
+        )
+    }
+}
+
+/// Memory taken by a single object. It is returned to the pool when this is
+/// dropped, so it must outlive everything constructed on top of it.
+pub struct Allocation {
+    offset: usize,
+    length: usize,
+    instance: usize,
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        let mut pool = POOL.lock().unwrap();
+        if let Some(taken) = pool.usage.get_mut(&self.instance) {
+            *taken -= self.length * WORD;
+        }
+        let memory = pool.memory.as_mut().unwrap();
+        release(&mut memory.free, self.offset, self.length);
+    }
+}
+
+/// Set the size of the pool in bytes. Fails once the pool is allocated.
+pub fn resize(size: usize) -> Result<(), &'static str> {
+    let mut pool = POOL.lock().unwrap();
+    if pool.memory.is_some() {
+        return Err("the pool can be only resized before the first tape is created");
+    }
+    pool.size = size;
+    Ok(())
+}
+
+/// Take the given number of words from the pool, allocating the pool on the
+/// first call, and let the constructor build on top of them. The memory is
+/// accounted to the current Pure Data instance.
+///
+/// An object that would not fit is refused without calling the constructor.
+/// The constructor runs with the pool unlocked. If it panics, the memory is
+/// returned while unwinding.
+pub fn allocate<T>(
+    words: usize,
+    constructor: impl FnOnce(&mut MemoryManager) -> T,
+) -> Result<(T, Allocation), Shortage> {
+    let (extent, allocation) = {
+        let mut pool = POOL.lock().unwrap();
+        let size = pool.size;
+        let memory = pool.memory.get_or_insert_with(|| {
+            let words = size / WORD;
+            let mut memory: Vec<MaybeUninit<u32>> = Vec::with_capacity(words);
+            // Safety: Elements of `MaybeUninit` do not require initialization.
+            unsafe { memory.set_len(words) };
+            let memory = Box::leak(memory.into_boxed_slice());
+            Memory {
+                start: memory.as_ptr() as usize,
+                free: vec![(0, words)],
+            }
+        });
+
+        let Some(offset) = memory
+            .free
+            .iter()
+            .find(|(_, length)| *length >= words)
+            .map(|(offset, _)| *offset)
+        else {
+            let largest = memory.free.iter().map(|(_, length)| *length).max();
+            return Err(Shortage {
+                required: words * WORD,
+                available: largest.unwrap_or(0) * WORD,
+                size,
+            });
+        };
+
+        take(&mut memory.free, offset, words);
+        // Safety: The extent was free, so it does not overlap memory taken by
+        // any other object. The pool is never deallocated.
+        let extent = unsafe {
+            std::slice::from_raw_parts_mut(
+                (memory.start as *mut MaybeUninit<u32>).add(offset),
+                words,
+            )
+        };
+        *pool.usage.entry(instance::current()).or_insert(0) += words * WORD;
+
+        let allocation = Allocation {
+            offset,
+            length: words,
+            instance: instance::current(),
+        };
+        (extent, allocation)
+    };
+
+    let result = constructor(&mut MemoryManager::from(extent));
+    Ok((result, allocation))
+}
+
+pub fn usage() -> Usage {
+    let pool = POOL.lock().unwrap();
+    let total = pool.memory.as_ref().map_or(0, |memory| {
+        let free: usize = memory.free.iter().map(|(_, length)| length).sum();
+        pool.size / WORD * WORD - free * WORD
+    });
+    let instance = pool.usage.get(&instance::current()).copied().unwrap_or(0);
+    Usage {
+        instance,
+        total,
+        size: pool.size,
+    }
+}
+
+/// Remove the start of the free extent at the given offset.
+fn take(free: &mut Vec<(usize, usize)>, offset: usize, length: usize) {
+    let index = free.iter().position(|(o, _)| *o == offset).unwrap();
+    if free[index].1 == length {
+        free.remove(index);
+    } else {
+        free[index] = (offset + length, free[index].1 - length);
+    }
+}
+
+/// Add the extent back, merging it with free neighbours.
+fn release(free: &mut Vec<(usize, usize)>, offset: usize, length: usize) {
+    if length == 0 {
+        return;
+    }
+    let index = free.partition_point(|(o, _)| *o < offset);
+    free.insert(index, (offset, length));
+    if index + 1 < free.len() && offset + length == free[index + 1].0 {
+        free[index].1 += free.remove(index + 1).1;
+    }
+    if index > 0 && free[index - 1].0 + free[index - 1].1 == offset {
+        free[index - 1].1 += free.remove(index).1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_merges_released_extents_with_free_neighbours() {
+        let mut free = vec![(0, 100)];
+        take(&mut free, 0, 10);
+        take(&mut free, 10, 20);
+        take(&mut free, 30, 70);
+        assert!(free.is_empty());
+
+        release(&mut free, 10, 20);
+        assert_eq!(free, vec![(10, 20)]);
+        release(&mut free, 30, 70);
+        assert_eq!(free, vec![(10, 90)]);
+        release(&mut free, 0, 10);
+        assert_eq!(free, vec![(0, 100)]);
+    }
+}
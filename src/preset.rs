@@ -0,0 +1,341 @@
+//! Snapshots of parameters of an object, morphing between them and their
+//! randomization.
+
+use std::collections::HashMap;
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::wrapper::{Kind, Parameter};
+
+/// Time constant of the glide towards morphed values, in seconds. The glide
+/// moves in steps of a few frames, see `Controls::step`.
+const GLIDE: f32 = 0.01;
+
+/// Values of parameters are recorded as they are set, since the instruments
+/// do not expose them. Parameters that were never set are left out of
+/// snapshots, so recalling them keeps their current value.
+pub struct Presets {
+    values: Vec<Option<f32>>,
+    targets: Vec<Option<f32>>,
+    gliding: bool,
+    slots: HashMap<u32, Vec<Option<f32>>>,
+    locked: Vec<bool>,
+    undo: Option<Vec<Option<f32>>>,
+    random: StdRng,
+}
+
+impl Presets {
+    pub fn new(number_of_parameters: usize) -> Self {
+        Self {
+            values: vec![None; number_of_parameters],
+            targets: vec![None; number_of_parameters],
+            gliding: false,
+            slots: HashMap::new(),
+            locked: vec![false; number_of_parameters],
+            undo: None,
+            random: StdRng::from_entropy(),
+        }
+    }
+
+    /// Record a value set from outside. It stops any glide of the parameter.
+    pub fn set(&mut self, index: usize, value: f32) {
+        self.values[index] = Some(value);
+        self.targets[index] = None;
+    }
+
+    /// Last known value of the parameter.
+    pub fn value(&self, index: usize) -> Option<f32> {
+        self.values[index]
+    }
+
+    pub fn store<T>(&mut self, slot: u32, parameters: &[Parameter<T>]) {
+        let snapshot = self.snapshot(parameters);
+        self.slots.insert(slot, snapshot);
+    }
+
+    /// Known values of all parameters except settings.
+    pub fn snapshot<T>(&self, parameters: &[Parameter<T>]) -> Vec<Option<f32>> {
+        self.values
+            .iter()
+            .zip(parameters)
+            .map(|(value, parameter)| value.filter(|_| parameter.kind != Kind::Setting))
+            .collect()
+    }
+
+    pub fn recall<T>(
+        &mut self,
+        slot: u32,
+        state: &mut T,
+        parameters: &[Parameter<T>],
+    ) -> Result<(), String> {
+        let snapshot = self
+            .slots
+            .get(&slot)
+            .ok_or_else(|| format!("slot {} is empty", slot))?;
+        for (i, value) in snapshot.iter().enumerate() {
+            if let Some(value) = *value {
+                (parameters[i].set)(state, value);
+                self.values[i] = Some(value);
+                self.targets[i] = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Continuous parameters and inputs glide towards the interpolated value,
+    /// discrete ones switch over once the amount crosses the middle.
+    pub fn morph<T>(
+        &mut self,
+        (a, b): (u32, u32),
+        amount: f32,
+        state: &mut T,
+        parameters: &[Parameter<T>],
+    ) -> Result<(), String> {
+        let empty = |slot| format!("slot {} is empty", slot);
+        let snapshot_a = self.slots.get(&a).ok_or_else(|| empty(a))?;
+        let snapshot_b = self.slots.get(&b).ok_or_else(|| empty(b))?;
+        let amount = amount.clamp(0.0, 1.0);
+
+        for (i, parameter) in parameters.iter().enumerate() {
+            let value = match (snapshot_a[i], snapshot_b[i]) {
+                (Some(x), Some(y)) if parameter.kind.is_gradual() => x + (y - x) * amount,
+                (Some(x), Some(y)) => {
+                    if amount < 0.5 {
+                        x
+                    } else {
+                        y
+                    }
+                }
+                (Some(x), None) | (None, Some(x)) => x,
+                (None, None) => continue,
+            };
+
+            if parameter.kind.is_gradual() {
+                self.targets[i] = Some(value);
+                self.gliding = true;
+            } else if self.values[i] != Some(value) {
+                (parameter.set)(state, value);
+                self.values[i] = Some(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keep the parameter untouched by randomization.
+    pub fn lock(&mut self, index: usize, locked: bool) {
+        self.locked[index] = locked;
+    }
+
+    /// Move every unlocked continuous parameter that was set towards a random
+    /// value of its range. The amount of 1 picks a completely new value.
+    /// Passing a seed makes this and all the following randomizations
+    /// reproducible.
+    pub fn randomize<T>(
+        &mut self,
+        amount: f32,
+        seed: Option<u64>,
+        state: &mut T,
+        parameters: &[Parameter<T>],
+    ) {
+        if let Some(seed) = seed {
+            self.random = StdRng::seed_from_u64(seed);
+        }
+        let amount = amount.clamp(0.0, 1.0);
+        self.undo = Some(self.values.clone());
+
+        for (i, parameter) in parameters.iter().enumerate() {
+            if parameter.kind != Kind::Continuous || self.locked[i] {
+                continue;
+            }
+            // The instrument does not expose values never set, there is
+            // nothing to perturb nor to return to.
+            let Some(current) = self.values[i] else {
+                continue;
+            };
+            let (min, max) = (*parameter.range.start(), *parameter.range.end());
+            let current = current.clamp(min, max);
+            let random = self.random.gen_range(min..=max);
+            let value = current + (random - current) * amount;
+            (parameter.set)(state, value);
+            self.values[i] = Some(value);
+            self.targets[i] = None;
+        }
+    }
+
+    /// Return parameters to where they were before the last randomization.
+    pub fn undo<T>(&mut self, state: &mut T, parameters: &[Parameter<T>]) -> Result<(), String> {
+        let previous = self
+            .undo
+            .take()
+            .ok_or_else(|| "there is nothing to undo".to_string())?;
+        for (i, value) in previous.into_iter().enumerate() {
+            if let Some(value) = value {
+                if self.values[i] != Some(value) {
+                    (parameters[i].set)(state, value);
+                }
+                self.values[i] = Some(value);
+                self.targets[i] = None;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_gliding(&self) -> bool {
+        self.gliding
+    }
+
+    /// Move gliding parameters closer to their targets by the given time.
+    pub fn advance<T>(&mut self, state: &mut T, parameters: &[Parameter<T>], elapsed: f32) {
+        if !self.gliding {
+            return;
+        }
+
+        let coefficient = 1.0 - (-elapsed / GLIDE).exp();
+        self.gliding = false;
+
+        for (i, parameter) in parameters.iter().enumerate() {
+            let Some(target) = self.targets[i] else {
+                continue;
+            };
+            let current = self.values[i].unwrap_or(target);
+            let mut value = current + (target - current) * coefficient;
+            if (target - value).abs() < 1e-4 {
+                value = target;
+                self.targets[i] = None;
+            } else {
+                self.gliding = true;
+            }
+            (parameter.set)(state, value);
+            self.values[i] = Some(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrapper::{FloatMethod, PdFloat};
+
+    unsafe extern "C" fn noop(_object: *mut std::os::raw::c_void, _value: PdFloat) {}
+
+    fn parameters() -> Vec<Parameter<[f32; 3]>> {
+        let parameter = |name, kind, set| Parameter {
+            name,
+            kind,
+            range: 0.0..=1.0,
+            method: noop as FloatMethod,
+            set,
+        };
+        vec![
+            parameter("knob", Kind::Continuous, |s, v| s[0] = v),
+            parameter("switch", Kind::Discrete, |s, v| s[1] = v),
+            parameter("bypass", Kind::Setting, |s, v| s[2] = v),
+        ]
+    }
+
+    #[test]
+    fn it_recalls_stored_values_except_settings() {
+        let parameters = parameters();
+        let mut presets = Presets::new(parameters.len());
+        let mut state = [0.0; 3];
+
+        presets.set(0, 0.2);
+        presets.set(1, 1.0);
+        presets.set(2, 1.0);
+        presets.store(1, &parameters);
+
+        assert_eq!(presets.recall(1, &mut state, &parameters), Ok(()));
+        assert_eq!(state, [0.2, 1.0, 0.0]);
+        assert_eq!(
+            presets.recall(2, &mut state, &parameters),
+            Err("slot 2 is empty".to_string())
+        );
+    }
+
+    #[test]
+    fn it_glides_continuous_and_switches_discrete_parameters() {
+        let parameters = parameters();
+        let mut presets = Presets::new(parameters.len());
+        let mut state = [0.0; 3];
+
+        presets.set(0, 0.0);
+        presets.set(1, 0.0);
+        presets.store(1, &parameters);
+        presets.set(0, 1.0);
+        presets.set(1, 1.0);
+        presets.store(2, &parameters);
+
+        presets.morph((1, 2), 0.4, &mut state, &parameters).unwrap();
+        assert_eq!(state[1], 0.0);
+        presets.morph((1, 2), 0.6, &mut state, &parameters).unwrap();
+        assert_eq!(state[1], 1.0);
+
+        presets.advance(&mut state, &parameters, 0.001);
+        assert!(state[0] > 0.6 && state[0] < 1.0);
+        for _ in 0..100 {
+            presets.advance(&mut state, &parameters, 0.001);
+        }
+        assert_eq!(state[0], 0.6);
+    }
+
+    #[test]
+    fn it_randomizes_unlocked_continuous_parameters_and_undoes_it() {
+        let mut all = parameters();
+        all.push(Parameter {
+            range: -5.0..=5.0,
+            set: |_, _| {},
+            ..parameters().remove(0)
+        });
+        let parameters = all;
+        let randomized = |lock| {
+            let mut presets = Presets::new(parameters.len());
+            let mut state = [0.0; 3];
+            for (i, value) in [0.2, 1.0, 1.0, 0.0].into_iter().enumerate() {
+                presets.set(i, value);
+            }
+            presets.lock(0, lock);
+            presets.randomize(0.5, Some(1), &mut state, &parameters);
+            (presets, state)
+        };
+
+        let (mut presets, mut state) = randomized(false);
+        assert!(state[0] != 0.0 && (0.1..=0.6).contains(&state[0]));
+        assert_eq!(state[1..], [0.0, 0.0]);
+        let other = presets.values[3].unwrap();
+        assert!(other != 0.0 && (-2.5..=2.5).contains(&other));
+        assert_eq!(randomized(false).1, state);
+
+        assert_eq!(presets.undo(&mut state, &parameters), Ok(()));
+        assert_eq!(state[0], 0.2);
+        assert!(presets.undo(&mut state, &parameters).is_err());
+
+        let (presets, state) = randomized(true);
+        assert_eq!(state[0], 0.0);
+        assert_eq!(presets.values[0], Some(0.2));
+    }
+
+    #[test]
+    fn it_leaves_parameters_never_set_and_inputs_out_of_randomization() {
+        let mut all = parameters();
+        all.push(Parameter {
+            kind: Kind::Input,
+            set: |s, v| s[2] = v,
+            ..parameters().remove(0)
+        });
+        let parameters = all;
+        let mut presets = Presets::new(parameters.len());
+        let mut state = [0.0; 3];
+
+        presets.set(3, 0.5);
+        presets.randomize(1.0, Some(1), &mut state, &parameters);
+        assert_eq!(state, [0.0; 3]);
+        assert_eq!(presets.values[0], None);
+        assert_eq!(presets.values[3], Some(0.5));
+
+        assert_eq!(presets.undo(&mut state, &parameters), Ok(()));
+        assert_eq!(state, [0.0; 3]);
+        assert_eq!(presets.values[0], None);
+    }
+}
@@ -0,0 +1,287 @@
+//! Offline rendering of the instruments, used by `automaton-render`.
+//!
+//! Instruments are driven by a script of timed messages, one per line:
+//!
+//! ```text
+//! # Comments and empty lines are ignored.
+//! t=0 achordion wavetable_bank 0.3
+//! t=0.5 kaseta head_1_feedback 0.8
+//! ```
+//!
+//! Times are in seconds, selectors are the same as the ones accepted by the
+//! objects in Pure Data. Every instrument mentioned in the script is created
+//! once. When both are, achordion~ is played through kaseta~.
+//!
+//! Messages are applied between processed blocks, here 32 frames long. A
+//! message timed inside a block takes effect at the start of the next one.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::instruments::achordion::Achordion;
+use crate::instruments::kaseta::Kaseta;
+use crate::wrapper::{self, Context, PdClass};
+
+/// Number of frames processed between two messages or control ticks.
+const BLOCK: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instrument {
+    Achordion,
+    Kaseta,
+}
+
+impl FromStr for Instrument {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "achordion" | "achordion~" => Ok(Self::Achordion),
+            "kaseta" | "kaseta~" => Ok(Self::Kaseta),
+            _ => Err(format!("unknown instrument {:?}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub time: f64,
+    pub instrument: Instrument,
+    pub parameter: String,
+    pub value: f32,
+}
+
+impl Event {
+    /// Index of the frame the event is due at. Rounding keeps times written
+    /// from frame indices, like those of recordings, exact.
+    pub fn frame(&self, sample_rate: u32) -> usize {
+        (self.time * f64::from(sample_rate)).round() as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Script {
+    events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut events = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let event = parse_event(line).map_err(|message| ScriptError {
+                line: i + 1,
+                message,
+            })?;
+            events.push(event);
+        }
+
+        // The sort is stable, messages sent at the same time keep their order.
+        events.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+
+        Ok(Self { events })
+    }
+}
+
+fn parse_event(line: &str) -> Result<Event, String> {
+    let tokens: Vec<_> = line.split_whitespace().collect();
+    if tokens.len() != 4 {
+        return Err("expected `t=<seconds> <instrument> <parameter> <value>`".to_string());
+    }
+
+    let time = tokens[0]
+        .strip_prefix("t=")
+        .ok_or_else(|| format!("expected time, got {:?}", tokens[0]))?;
+    let time: f64 = time
+        .parse()
+        .map_err(|_| format!("invalid time {:?}", time))?;
+    if !time.is_finite() || time < 0.0 {
+        return Err(format!("time must not be negative, got {}", time));
+    }
+
+    let instrument: Instrument = tokens[1].parse()?;
+
+    let parameter = tokens[2];
+    if !is_parameter(instrument, parameter) {
+        return Err(format!("{:?} has no parameter {:?}", tokens[1], parameter));
+    }
+
+    let value = tokens[3]
+        .parse()
+        .map_err(|_| format!("invalid value {:?}", tokens[3]))?;
+
+    Ok(Event {
+        time,
+        instrument,
+        parameter: parameter.to_string(),
+        value,
+    })
+}
+
+fn is_parameter(instrument: Instrument, name: &str) -> bool {
+    match instrument {
+        Instrument::Achordion => Achordion::PARAMETERS.iter().any(|p| p.name == name),
+        Instrument::Kaseta => Kaseta::PARAMETERS.iter().any(|p| p.name == name),
+    }
+}
+
+impl Script {
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn uses(&self, instrument: Instrument) -> bool {
+        self.events.iter().any(|e| e.instrument == instrument)
+    }
+}
+
+/// Render the script into `number_of_frames` long channels.
+///
+/// The optional stereo input is fed to kaseta~ and padded with silence if
+/// shorter. The result is stereo when kaseta~ is used, otherwise it is the
+/// mono mix of achordion~. Fails when an instrument refuses to be created.
+pub fn render(
+    script: &Script,
+    input: Option<[&[f32]; 2]>,
+    sample_rate: u32,
+    number_of_frames: usize,
+) -> Result<Vec<Vec<f32>>, String> {
+    let mut context = Context::new(sample_rate as f32);
+    let mut achordion = script
+        .uses(Instrument::Achordion)
+        .then(|| Achordion::new(&mut context).map(Box::new))
+        .transpose()
+        .map_err(|e| format!("{}: {}", Achordion::NAME, e))?;
+    let mut kaseta = script
+        .uses(Instrument::Kaseta)
+        .then(|| Kaseta::new(&mut context).map(Box::new))
+        .transpose()
+        .map_err(|e| format!("{}: {}", Kaseta::NAME, e))?;
+
+    let number_of_channels = if kaseta.is_some() { 2 } else { 1 };
+    let mut output = vec![vec![0.0; number_of_frames]; number_of_channels];
+
+    let mut achordion_outlets = [[0.0; BLOCK]; 4];
+    let mut kaseta_inlets = [[0.0; BLOCK]; 2];
+    let mut kaseta_outlets = [[0.0; BLOCK]; 12];
+
+    let mut events = script.events().iter().peekable();
+
+    for start in (0..number_of_frames).step_by(BLOCK) {
+        while let Some(event) = events.next_if(|e| e.frame(sample_rate) <= start) {
+            let applied = match event.instrument {
+                Instrument::Achordion => wrapper::set_parameter(
+                    achordion.as_deref_mut().unwrap(),
+                    &event.parameter,
+                    event.value,
+                ),
+                Instrument::Kaseta => wrapper::set_parameter(
+                    kaseta.as_deref_mut().unwrap(),
+                    &event.parameter,
+                    event.value,
+                ),
+            };
+            debug_assert!(applied, "parameters are validated while parsing");
+        }
+
+        let length = BLOCK.min(number_of_frames - start);
+
+        if let Some(achordion) = achordion.as_deref_mut() {
+            perform(achordion, &[[0.0; BLOCK]; 2], &mut achordion_outlets);
+        }
+
+        if let Some(kaseta) = kaseta.as_deref_mut() {
+            for (channel, inlet) in kaseta_inlets.iter_mut().enumerate() {
+                for (i, sample) in inlet.iter_mut().enumerate() {
+                    *sample = input
+                        .and_then(|input| input[channel].get(start + i).copied())
+                        .unwrap_or(0.0);
+                    if achordion.is_some() {
+                        *sample += achordion_outlets[0][i];
+                    }
+                }
+            }
+
+            // The hardware ticks its control loop roughly once per processed
+            // buffer, do the same.
+            kaseta.tick();
+            perform(kaseta, &kaseta_inlets, &mut kaseta_outlets);
+
+            output[0][start..start + length].copy_from_slice(&kaseta_outlets[0][..length]);
+            output[1][start..start + length].copy_from_slice(&kaseta_outlets[1][..length]);
+        } else {
+            output[0][start..start + length].copy_from_slice(&achordion_outlets[0][..length]);
+        }
+    }
+
+    Ok(output)
+}
+
+fn perform<T: PdClass, const I: usize, const O: usize>(
+    instrument: &mut T,
+    inlets: &[[f32; BLOCK]; I],
+    outlets: &mut [[f32; BLOCK]; O],
+) {
+    let inlets: Vec<&[f32]> = inlets.iter().map(|i| &i[..]).collect();
+    let mut outlets: Vec<&mut [f32]> = outlets.iter_mut().map(|o| &mut o[..]).collect();
+    instrument.perform(BLOCK, &inlets, &mut outlets);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_script_sorted_by_time() {
+        let script: Script = "
+            # Comment
+            t=0.5 kaseta head_1_feedback 0.8
+
+            t=0 achordion~ detune 0.2
+            t=0.5 kaseta head_1_volume 1
+        "
+        .parse()
+        .unwrap();
+
+        let events = script.events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].instrument, Instrument::Achordion);
+        assert_eq!(events[1].parameter, "head_1_feedback");
+        assert_eq!(events[1].value, 0.8);
+        assert_eq!(events[2].parameter, "head_1_volume");
+        assert!(script.uses(Instrument::Kaseta));
+    }
+
+    #[test]
+    fn report_line_of_invalid_message() {
+        let error = "t=0 kaseta dry_wet 1\nt=1 kaseta flux 1"
+            .parse::<Script>()
+            .unwrap_err();
+        assert_eq!(error.line, 2);
+
+        assert!("0 kaseta dry_wet 1".parse::<Script>().is_err());
+        assert!("t=0 kaseta dry_wet".parse::<Script>().is_err());
+        assert!("t=-1 kaseta dry_wet 1".parse::<Script>().is_err());
+        assert!("t=0 sirena dry_wet 1".parse::<Script>().is_err());
+    }
+}
@@ -1,108 +1,1574 @@
-#[macro_export]
-macro_rules! register_dsp_method {
-    ( $class:ident, receiver = $receiver:ty, dummy_offset = $offset:expr, number_of_inlets = $inlets:expr, number_of_outlets = $outlets:expr, callback = $perform:expr ) => {
-        register_dsp_method($class);
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::{Range, RangeInclusive};
+use std::os::raw::{c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-        unsafe fn register_dsp_method(class: *mut pd_sys::_class) {
-            assert!($inlets >= 1, "number of inlets must be set to >= 1, pure data always register one inlet, even when it's not used");
+use crate::automation::Recorder;
+use crate::choice::{Choice, Reporter};
+use crate::clock::PdClock;
+use crate::controls::Controls;
+use crate::log::{self, Logger};
+use crate::meter::Meter;
+use crate::midi::Bindings;
+use crate::osc::Inbox;
+use crate::preset::Presets;
+use crate::{cstr, instance};
 
-            pd_sys::class_addmethod(
-                class,
-                Some(std::mem::transmute::<
-                    unsafe extern "C" fn(*mut $receiver, *mut *mut pd_sys::t_signal),
-                    _,
-                >(__dsp_method)),
-                pd_sys::gensym(cstr::cstr("dsp").as_ptr()),
-                pd_sys::t_atomtype::A_CANT,
-                0,
-            );
+/// Maximum number of signal inlets or outlets a class can declare.
+pub const MAX_SIGNALS: usize = 16;
+
+/// Float and sample type of the Pure Data the library is built for. The
+/// bindings in `pd_sys` always assume single precision, so everything
+/// touching floats goes through the wrapper, converting to `f32` used by the
+/// DSP code.
+#[cfg(not(feature = "pd64"))]
+pub type PdFloat = f32;
+#[cfg(feature = "pd64")]
+pub type PdFloat = f64;
+
+#[cfg(feature = "pd64")]
+mod pd64 {
+    extern "C" {
+        pub fn sys_getsr() -> f64;
+        pub fn signalinlet_new(owner: *mut pd_sys::t_object, f: f64) -> *mut pd_sys::t_inlet;
+    }
+}
+
+/// Pure Data class implemented in safe Rust.
+///
+/// The implementor holds the state of a single object. The wrapper takes care
+/// of allocating it inside of a Pure Data object, creating its signal outlets,
+/// dispatching registered methods and running the DSP callback.
+pub trait PdClass: Sized + 'static {
+    const NAME: &'static str;
+    const SIGNAL_INLETS: usize = 0;
+    const SIGNAL_OUTLETS: usize = 0;
+
+    /// Values of secondary signal inlets while no signal is connected to
+    /// them. Inlets with a value set also accept floats, the way the main one
+    /// does, others only take signals.
+    const SIGNAL_INLET_FALLBACKS: &'static [Option<f32>] = &[];
+
+    /// Let Pure Data 0.54 and newer carry multiple channels through a single
+    /// signal connection. On older versions all signals stay mono.
+    const MULTICHANNEL: bool = false;
 
-            pd_sys::class_domainsignalin(class, $offset.get_byte_offset() as c_int);
+    /// Float parameters, each settable by a message of its name. Listing
+    /// them here rather than in `register` makes them available to the
+    /// offline renderer too.
+    const PARAMETERS: &'static [Parameter<Self>] = &[];
+
+    /// Number of frames the object processes at once. Parameters changing
+    /// within a block are applied at multiples of it.
+    const GRANULARITY: usize = 1;
+
+    /// Discrete parameters selectable by name. Names of their active values
+    /// are reported through a control outlet placed after all others.
+    const CHOICES: &'static [Choice] = &[];
+
+    /// Refusing creation, e.g. for lack of memory, returns a message to be
+    /// logged. Pure Data then reports the object as failed to create.
+    fn new(context: &mut Context) -> Result<Self, String>;
+
+    fn register(_class: &mut Class<Self>) {}
+
+    /// Number of channels produced by the given outlet, asked on every DSP
+    /// graph update of a multichannel class.
+    fn signal_outlet_channels(&self, _outlet: usize, _inlet_channels: &[usize]) -> usize {
+        1
+    }
+
+    /// Channels of a multichannel signal are passed one after another, each
+    /// of them `number_of_frames` long.
+    fn perform(
+        &mut self,
+        _number_of_frames: usize,
+        _inlets: &[&[f32]],
+        _outlets: &mut [&mut [f32]],
+    ) {
+    }
+}
+
+pub type FloatMethod = unsafe extern "C" fn(*mut c_void, PdFloat);
+pub type BangMethod = unsafe extern "C" fn(*mut c_void);
+pub type GimmeMethod =
+    unsafe extern "C" fn(*mut c_void, *mut pd_sys::t_symbol, c_int, *mut pd_sys::t_atom);
+
+#[macro_export]
+macro_rules! bang_method {
+    ( $method:expr ) => {{
+        unsafe extern "C" fn __bang_method(object: *mut std::os::raw::c_void) {
+            $crate::wrapper::call_bang_method(object, $method);
         }
+        __bang_method as $crate::wrapper::BangMethod
+    }};
+}
 
-        unsafe extern "C" fn __dsp_method(
-            receiver: *mut $receiver,
-            signal: *mut *mut pd_sys::t_signal,
+/// Method accepting any number of float and symbol arguments.
+#[macro_export]
+macro_rules! gimme_method {
+    ( $method:expr ) => {{
+        unsafe extern "C" fn __gimme_method(
+            object: *mut std::os::raw::c_void,
+            _selector: *mut pd_sys::t_symbol,
+            argc: std::os::raw::c_int,
+            argv: *mut pd_sys::t_atom,
         ) {
-            let iolets = $inlets + $outlets;
+            $crate::wrapper::call_gimme_method(object, argc, argv, $method);
+        }
+        __gimme_method as $crate::wrapper::GimmeMethod
+    }};
+}
 
-            let vector_length = {
-                let receiver = 1;
-                let number_of_frames = 1;
-                receiver + number_of_frames + iolets
-            };
+#[macro_export]
+macro_rules! parameter {
+    ( $name:expr, $method:expr ) => {
+        parameter!($name, $method, Continuous)
+    };
+    ( $name:expr, $method:expr, $kind:ident ) => {
+        parameter!($name, $method, $kind, 0.0..=1.0)
+    };
+    ( $name:expr, $method:expr, $kind:ident, $range:expr ) => {{
+        unsafe extern "C" fn __parameter_method(
+            object: *mut std::os::raw::c_void,
+            value: $crate::wrapper::PdFloat,
+        ) {
+            $crate::wrapper::call_parameter_method(object, value, $name, $method);
+        }
+        $crate::wrapper::Parameter {
+            name: $name,
+            kind: $crate::wrapper::Kind::$kind,
+            range: $range,
+            method: __parameter_method as $crate::wrapper::FloatMethod,
+            set: $method,
+        }
+    }};
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Can take any value of its range, e.g. a knob.
+    Continuous,
+    /// Selects one of few options, e.g. a switch.
+    Discrete,
+    /// Configures the object rather than its sound, e.g. bypass. Settings
+    /// are not a part of presets.
+    Setting,
+    /// Continuous input normally driven by the patch, e.g. a V/Oct pitch.
+    /// Inputs are left out of randomization.
+    Input,
+}
+
+impl Kind {
+    /// Values between two others are valid, so the parameter can glide.
+    pub fn is_gradual(self) -> bool {
+        matches!(self, Kind::Continuous | Kind::Input)
+    }
+}
+
+pub struct Parameter<T> {
+    pub name: &'static str,
+    pub kind: Kind,
+    /// Values accepted by the instrument, 0 to 1 unless stated otherwise.
+    pub range: RangeInclusive<f32>,
+    pub method: FloatMethod,
+    pub set: fn(&mut T, f32),
+}
+
+/// Find parameter of the given name and set it, returning false if there is
+/// none.
+pub fn set_parameter<T: PdClass>(state: &mut T, name: &str, value: f32) -> bool {
+    match T::PARAMETERS
+        .iter()
+        .find(|parameter| parameter.name == name)
+    {
+        Some(parameter) => {
+            (parameter.set)(state, value);
+            true
+        }
+        None => false,
+    }
+}
+
+#[repr(C)]
+pub struct Object<T> {
+    pd_obj: pd_sys::t_object,
+    signal_dummy: PdFloat,
+    scratch: Vec<Vec<f32>>,
+    /// Inputs and outputs of a part of the block, while it is split.
+    parts: Vec<Vec<f32>>,
+    debug: bool,
+    meter: Arc<Meter>,
+    deadline: Duration,
+    /// Present only for classes with parameters.
+    controls: Option<Controls>,
+    state: T,
+}
+
+pub struct Context {
+    sample_rate: f32,
+    object: *mut pd_sys::t_object,
+    arguments: Vec<Atom<'static>>,
+    directory: PathBuf,
+}
+
+impl Context {
+    /// Context of an object living outside of Pure Data, e.g. in the offline
+    /// renderer.
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            object: std::ptr::null_mut(),
+            arguments: Vec::new(),
+            directory: PathBuf::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Arguments the object was created with.
+    pub fn arguments(&self) -> &[Atom<'static>] {
+        &self.arguments
+    }
 
-            let signal = std::slice::from_raw_parts(signal, iolets);
+    /// Directory of the patch, relative paths of files are resolved from it.
+    /// Objects living outside of Pure Data use the working directory.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
 
-            let number_of_frames = (*signal[0]).s_n as usize;
+    pub fn logger(&self) -> Logger {
+        Logger::new(self.object as *const c_void)
+    }
+
+    /// The Pure Data object, passed to methods called by its clocks. It is
+    /// null for objects living outside of Pure Data.
+    pub fn owner(&self) -> *mut c_void {
+        self.object as *mut c_void
+    }
+
+    /// Control outlets are placed after all signal outlets.
+    pub fn new_outlet(&mut self) -> Outlet {
+        if self.object.is_null() {
+            return Outlet(std::ptr::null_mut());
+        }
+        Outlet(unsafe { pd_sys::outlet_new(self.object, std::ptr::null_mut()) })
+    }
+}
+
+/// Control outlet. Outlets of objects living outside of Pure Data are not
+/// connected anywhere and drop all messages.
+pub struct Outlet(*mut pd_sys::t_outlet);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Atom<'a> {
+    Float(f32),
+    Symbol(&'a str),
+}
+
+impl Atom<'_> {
+    pub fn float(&self) -> Option<f32> {
+        match self {
+            Atom::Float(value) => Some(*value),
+            Atom::Symbol(_) => None,
+        }
+    }
+}
 
-            let vector_size = vector_length * std::mem::size_of::<*mut pd_sys::t_int>();
-            let vector_pointer = pd_sys::getbytes(vector_size);
-            assert!(
-                !vector_pointer.is_null(),
-                "null pointer from pd_sys::getbytes",
+impl Outlet {
+    pub fn anything(&self, selector: &str, atoms: &[Atom]) {
+        if self.0.is_null() {
+            return;
+        }
+        let mut atoms: Vec<_> = atoms.iter().map(to_pd_atom).collect();
+        unsafe {
+            pd_sys::outlet_anything(
+                self.0,
+                pd_sys::gensym(cstr::cstr(selector).as_ptr()),
+                atoms.len() as c_int,
+                atoms.as_mut_ptr(),
             );
+        }
+    }
+}
+
+/// Fill the array of the given name with values of the function, given the
+/// position within the array from 0 to 1, and redraw it. Returns false if
+/// there is no such array.
+pub fn draw_array(name: &str, f: impl Fn(f32) -> f32) -> bool {
+    unsafe {
+        let array = pd_sys::pd_findbyclass(
+            pd_sys::gensym(cstr::cstr(name).as_ptr()),
+            pd_sys::garray_class,
+        ) as *mut pd_sys::t_garray;
+        if array.is_null() {
+            return false;
+        }
 
-            let vector = vector_pointer as *mut *mut pd_sys::t_int;
-            let vector: &mut [*mut pd_sys::t_int] =
-                std::slice::from_raw_parts_mut(vector, vector_length);
+        let mut size: c_int = 0;
+        let mut words: *mut pd_sys::t_word = std::ptr::null_mut();
+        if pd_sys::garray_getfloatwords(array, &mut size, &mut words) == 0 {
+            return false;
+        }
+        for i in 0..size.max(0) as usize {
+            let value = f(i as f32 / size as f32);
+            // Words hold doubles with pd64.
+            (std::ptr::addr_of_mut!((*words.add(i)).w_float) as *mut PdFloat)
+                .write(value as PdFloat);
+        }
+        pd_sys::garray_redraw(array);
+    }
+    true
+}
 
-            vector[1] = number_of_frames as *mut pd_sys::t_int;
-            for i in 0..iolets {
-                vector[2 + i] = (*signal[i]).s_vec as *mut pd_sys::t_int;
+/// Symbols are never released by Pure Data, so their names can be borrowed
+/// for as long as needed. Atoms of other types are skipped.
+#[allow(clippy::unnecessary_cast)] // The cast is only needed with pd64.
+pub(crate) unsafe fn from_pd_atoms(argc: c_int, argv: *const pd_sys::t_atom) -> Vec<Atom<'static>> {
+    if argc <= 0 || argv.is_null() {
+        return Vec::new();
+    }
+    std::slice::from_raw_parts(argv, argc as usize)
+        .iter()
+        .filter_map(|atom| match atom.a_type {
+            pd_sys::t_atomtype::A_FLOAT => {
+                let value = (std::ptr::addr_of!(atom.a_w) as *const PdFloat).read();
+                Some(Atom::Float(value as f32))
             }
+            pd_sys::t_atomtype::A_SYMBOL => {
+                let name = std::ffi::CStr::from_ptr((*atom.a_w.w_symbol).s_name);
+                name.to_str().ok().map(Atom::Symbol)
+            }
+            _ => None,
+        })
+        .collect()
+}
 
-            vector[0] = receiver as *mut pd_sys::t_int;
+#[allow(clippy::unnecessary_cast)] // The cast is only needed with pd64.
+fn to_pd_atom(atom: &Atom) -> pd_sys::t_atom {
+    let mut pd_atom: pd_sys::t_atom = unsafe { std::mem::zeroed() };
+    match atom {
+        Atom::Float(value) => {
+            pd_atom.a_type = pd_sys::t_atomtype::A_FLOAT;
+            // The word is wide enough to keep a double on 64-bit platforms.
+            unsafe {
+                (std::ptr::addr_of_mut!(pd_atom.a_w) as *mut PdFloat).write(*value as PdFloat)
+            };
+        }
+        Atom::Symbol(symbol) => {
+            pd_atom.a_type = pd_sys::t_atomtype::A_SYMBOL;
+            pd_atom.a_w.w_symbol = unsafe { pd_sys::gensym(cstr::cstr(symbol).as_ptr()) };
+        }
+    }
+    pd_atom
+}
 
-            pd_sys::dsp_addv(
-                Some(__perform),
-                vector_length as c_int,
-                vector_pointer as *mut pd_sys::t_int,
+pub struct Class<T> {
+    class: *mut pd_sys::_class,
+    _state: PhantomData<T>,
+}
+
+impl<T: PdClass> Class<T> {
+    pub fn add_float_method(&mut self, symbol: &str, method: FloatMethod) {
+        unsafe {
+            pd_sys::class_addmethod(
+                self.class,
+                Some(std::mem::transmute::<FloatMethod, unsafe extern "C" fn()>(
+                    method,
+                )),
+                pd_sys::gensym(cstr::cstr(symbol).as_ptr()),
+                pd_sys::t_atomtype::A_FLOAT,
+                0,
             );
+        }
+    }
 
-            pd_sys::freebytes(vector_pointer, vector_size);
+    pub fn add_gimme_method(&mut self, symbol: &str, method: GimmeMethod) {
+        unsafe {
+            pd_sys::class_addmethod(
+                self.class,
+                Some(std::mem::transmute::<GimmeMethod, unsafe extern "C" fn()>(
+                    method,
+                )),
+                pd_sys::gensym(cstr::cstr(symbol).as_ptr()),
+                pd_sys::t_atomtype::A_GIMME,
+                0,
+            );
         }
+    }
 
-        unsafe extern "C" fn __perform(buffer_pointer: *mut pd_sys::t_int) -> *mut pd_sys::t_int {
-            let buffer_length = {
-                let reserved = 1;
-                let receiver = 1;
-                let number_of_frames = 1;
-                let inlets = $inlets.max(1);
-                let outlets = $outlets;
-                reserved + receiver + number_of_frames + inlets + outlets
-            };
+    /// Method of a message carrying no arguments.
+    pub fn add_method(&mut self, symbol: &str, method: BangMethod) {
+        unsafe {
+            pd_sys::class_addmethod(
+                self.class,
+                Some(std::mem::transmute::<BangMethod, unsafe extern "C" fn()>(
+                    method,
+                )),
+                pd_sys::gensym(cstr::cstr(symbol).as_ptr()),
+                pd_sys::t_atomtype::A_NULL,
+                0,
+            );
+        }
+    }
+
+    pub fn add_bang_method(&mut self, method: BangMethod) {
+        unsafe {
+            pd_sys::class_addbang(
+                self.class,
+                Some(std::mem::transmute::<BangMethod, unsafe extern "C" fn()>(
+                    method,
+                )),
+            );
+        }
+    }
+}
 
-            let arguments = std::slice::from_raw_parts(buffer_pointer, buffer_length);
+pub(crate) struct ClassPointer(pub(crate) *mut pd_sys::_class);
 
-            let receiver = arguments[1] as *mut $receiver;
+// Class pointers are only ever dereferenced by Pure Data.
+unsafe impl Send for ClassPointer {}
 
-            let number_of_frames = arguments[2] as usize;
+lazy_static! {
+    // Each Pure Data instance sharing the process registers its own classes.
+    static ref CLASSES: Mutex<HashMap<(usize, &'static str), ClassPointer>> =
+        Mutex::new(HashMap::new());
+    // Save functions the classes had before the library wrapped them. They
+    // are the same in every instance.
+    static ref SAVE_FUNCTIONS: Mutex<HashMap<&'static str, pd_sys::t_savefn>> =
+        Mutex::new(HashMap::new());
+}
 
-            let mut inlets: [&mut [pd_sys::t_float]; $inlets] = Default::default();
-            #[allow(clippy::reversed_empty_ranges)]
-            for i in 0..$inlets {
-                inlets[i] = $crate::wrapper::read_signal(arguments[3 + i], number_of_frames);
-            }
+fn class_pointer<T: PdClass>() -> *mut pd_sys::_class {
+    CLASSES
+        .lock()
+        .unwrap()
+        .get(&(instance::current(), T::NAME))
+        .expect("class must be registered before its objects are created")
+        .0
+}
 
-            let mut outlets: [&mut [pd_sys::t_float]; $outlets] = Default::default();
-            #[allow(clippy::reversed_empty_ranges)]
-            for i in 0..$outlets {
-                outlets[i] =
-                    $crate::wrapper::read_signal(arguments[3 + $inlets + i], number_of_frames);
+/// Names of classes registered in the current Pure Data instance.
+pub fn classes() -> Vec<&'static str> {
+    let current = instance::current();
+    let mut classes: Vec<_> = CLASSES
+        .lock()
+        .unwrap()
+        .keys()
+        .filter(|(instance, _)| *instance == current)
+        .map(|(_, name)| *name)
+        .collect();
+    classes.sort_unstable();
+    classes
+}
+
+#[derive(Clone)]
+pub struct ObjectInfo {
+    pub name: &'static str,
+    pub sample_rate: f32,
+    pub meter: Arc<Meter>,
+    /// Parameter changes to be applied before the next processed block,
+    /// present only for classes with parameters.
+    pub inbox: Option<Arc<Inbox>>,
+    /// Index of the parameter of the given name.
+    pub find_parameter: fn(&str) -> Option<usize>,
+    instance: usize,
+    address: usize,
+}
+
+lazy_static! {
+    // Objects alive in all Pure Data instances, in order of their creation.
+    static ref OBJECTS: Mutex<Vec<ObjectInfo>> = Mutex::new(Vec::new());
+}
+
+/// Objects alive in the current Pure Data instance.
+pub fn objects() -> Vec<ObjectInfo> {
+    objects_of(instance::current())
+}
+
+/// Objects alive in the given Pure Data instance, for threads other than
+/// the one of the instance.
+pub fn objects_of(instance: usize) -> Vec<ObjectInfo> {
+    OBJECTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|object| object.instance == instance)
+        .cloned()
+        .collect()
+}
+
+fn find_parameter<T: PdClass>(name: &str) -> Option<usize> {
+    T::PARAMETERS.iter().position(|p| p.name == name)
+}
+
+pub fn is_registered<T: PdClass>() -> bool {
+    CLASSES
+        .lock()
+        .unwrap()
+        .contains_key(&(instance::current(), T::NAME))
+}
+
+/// Registering a class already known to the current Pure Data instance is
+/// a no-op.
+pub unsafe fn register_class<T: PdClass>() {
+    if is_registered::<T>() {
+        return;
+    }
+
+    log::debug(&format!("[{}] initializing", T::NAME));
+
+    let is_dsp = T::SIGNAL_INLETS + T::SIGNAL_OUTLETS > 0;
+    if is_dsp {
+        assert!(T::SIGNAL_INLETS >= 1, "number of inlets must be set to >= 1, pure data always register one inlet, even when it's not used");
+    }
+    assert!(
+        T::SIGNAL_INLET_FALLBACKS.len() < T::SIGNAL_INLETS.max(1),
+        "fallbacks can be only set for secondary signal inlets"
+    );
+    assert!(T::SIGNAL_INLETS <= MAX_SIGNALS, "too many signal inlets");
+    assert!(T::SIGNAL_OUTLETS <= MAX_SIGNALS, "too many signal outlets");
+
+    let class = pd_sys::class_new(
+        pd_sys::gensym(cstr::cstr(T::NAME).as_ptr()),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(*mut pd_sys::t_symbol, c_int, *mut pd_sys::t_atom) -> *mut c_void,
+            unsafe extern "C" fn() -> *mut c_void,
+        >(new::<T>)),
+        free_method::<Object<T>>(),
+        std::mem::size_of::<Object<T>>(),
+        class_flags::<T>(),
+        pd_sys::t_atomtype::A_GIMME,
+        0,
+    );
+
+    if is_dsp {
+        pd_sys::class_addmethod(
+            class,
+            Some(std::mem::transmute::<
+                unsafe extern "C" fn(*mut Object<T>, *mut *mut pd_sys::t_signal),
+                unsafe extern "C" fn(),
+            >(dsp::<T>)),
+            pd_sys::gensym(cstr::cstr("dsp").as_ptr()),
+            pd_sys::t_atomtype::A_CANT,
+            0,
+        );
+
+        pd_sys::class_domainsignalin(
+            class,
+            offset_of!(Object<T> => signal_dummy).get_byte_offset() as c_int,
+        );
+    }
+
+    CLASSES
+        .lock()
+        .unwrap()
+        .insert((instance::current(), T::NAME), ClassPointer(class));
+
+    let mut class = Class {
+        class,
+        _state: PhantomData,
+    };
+    class.add_float_method("debug", set_debug::<T>);
+    if is_dsp {
+        class.add_method("cpu", report_cpu::<T>);
+    }
+    if !T::PARAMETERS.is_empty() {
+        class.add_float_method("store", store::<T>);
+        class.add_float_method("recall", recall::<T>);
+        class.add_gimme_method("morph", morph::<T>);
+        class.add_gimme_method("randomize", randomize::<T>);
+        class.add_method("undo", undo::<T>);
+        class.add_gimme_method("lock", lock::<T>);
+        class.add_gimme_method("unlock", unlock::<T>);
+        class.add_gimme_method("learn", learn::<T>);
+        class.add_gimme_method("unlearn", unlearn::<T>);
+        class.add_gimme_method("map", map::<T>);
+        class.add_gimme_method("cc", cc::<T>);
+        SAVE_FUNCTIONS
+            .lock()
+            .unwrap()
+            .insert(T::NAME, pd_sys::class_getsavefn(class.class));
+        pd_sys::class_setsavefn(class.class, Some(save::<T>));
+        class.add_method("rec", rec::<T>);
+        class.add_method("stop", stop::<T>);
+        class.add_method("play", play::<T>);
+        class.add_method("loop", play_loop::<T>);
+        class.add_gimme_method("write", write::<T>);
+        class.add_gimme_method("read", read::<T>);
+    }
+    for parameter in T::PARAMETERS {
+        // A choice named after its parameter takes its floats too.
+        if T::CHOICES.iter().any(|c| c.selector == parameter.name) {
+            continue;
+        }
+        class.add_float_method(parameter.name, parameter.method);
+    }
+    for choice in T::CHOICES {
+        assert!(
+            find_parameter::<T>(choice.parameter).is_some(),
+            "choice must select a parameter"
+        );
+        class.add_gimme_method(choice.selector, choose::<T>);
+    }
+    T::register(&mut class);
+}
+
+pub fn sample_rate() -> f32 {
+    #[cfg(not(feature = "pd64"))]
+    let sample_rate = unsafe { pd_sys::sys_getsr() };
+    #[cfg(feature = "pd64")]
+    let sample_rate = unsafe { pd64::sys_getsr() } as f32;
+    sample_rate
+}
+
+fn class_flags<T: PdClass>() -> c_int {
+    if T::MULTICHANNEL && multichannel_supported() {
+        CLASS_MULTICHANNEL
+    } else {
+        pd_sys::CLASS_DEFAULT as c_int
+    }
+}
+
+unsafe extern "C" fn new<T: PdClass>(
+    _name: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) -> *mut c_void {
+    let object = pd_sys::pd_new(class_pointer::<T>()) as *mut Object<T>;
+
+    for i in 1..T::SIGNAL_INLETS {
+        new_signal_inlet(
+            &mut (*object).pd_obj,
+            T::SIGNAL_INLET_FALLBACKS.get(i - 1).copied().flatten(),
+        );
+    }
+
+    for _ in 0..T::SIGNAL_OUTLETS {
+        pd_sys::outlet_new(&mut (*object).pd_obj, &mut pd_sys::s_signal);
+    }
+
+    let directory = std::ffi::CStr::from_ptr((*pd_sys::canvas_getcurrentdir()).s_name);
+    let mut context = Context {
+        sample_rate: sample_rate(),
+        object: &mut (*object).pd_obj,
+        arguments: from_pd_atoms(argc, argv),
+        directory: PathBuf::from(&*directory.to_string_lossy()),
+    };
+    let state = match T::new(&mut context) {
+        Ok(state) => state,
+        Err(message) => {
+            Logger::new(object as *const c_void).error(&format!("[{}] {}", T::NAME, message));
+            pd_sys::pd_free(object as *mut pd_sys::t_pd);
+            return std::ptr::null_mut();
+        }
+    };
+
+    let controls = (!T::PARAMETERS.is_empty()).then(|| {
+        let mut controls = Controls::new(T::PARAMETERS.len(), T::GRANULARITY, context.sample_rate);
+        controls.bindings = Some(Bindings::new(object as *mut pd_sys::t_pd));
+        controls.directory = context.directory.clone();
+        if !T::CHOICES.is_empty() {
+            let clock = PdClock::new(object as *mut c_void, report_choices::<T>);
+            controls.choices = Some(Reporter::new(
+                context.new_outlet(),
+                Box::new(clock),
+                T::CHOICES.len(),
+            ));
+        }
+        controls
+    });
+
+    let meter = Arc::new(Meter::default());
+    OBJECTS.lock().unwrap().push(ObjectInfo {
+        name: T::NAME,
+        sample_rate: context.sample_rate,
+        meter: Arc::clone(&meter),
+        inbox: controls.as_ref().map(|c| Arc::clone(&c.inbox)),
+        find_parameter: find_parameter::<T>,
+        instance: instance::current(),
+        address: object as usize,
+    });
+    std::ptr::addr_of_mut!((*object).meter).write(meter);
+    std::ptr::addr_of_mut!((*object).deadline).write(Duration::ZERO);
+    std::ptr::addr_of_mut!((*object).controls).write(controls);
+
+    std::ptr::addr_of_mut!((*object).scratch).write(Vec::new());
+    std::ptr::addr_of_mut!((*object).parts).write(Vec::new());
+
+    // The memory handed over by Pure Data is zeroed, the state must be
+    // written without dropping its previous value.
+    std::ptr::addr_of_mut!((*object).state).write(state);
+    (*object).debug = false;
+
+    object as *mut c_void
+}
+
+/// Report DSP load of the object on the console.
+unsafe extern "C" fn report_cpu<T: PdClass>(object: *mut c_void) {
+    let object = object as *mut Object<T>;
+    let meter = &(*object).meter;
+    Logger::new(object as *const c_void).info(&format!(
+        "[{}] cpu: {:.2}% average, {:.2}% peak",
+        T::NAME,
+        meter.average(),
+        meter.peak()
+    ));
+}
+
+/// Store current values of parameters into the given slot.
+#[allow(clippy::unnecessary_cast)] // The cast is only needed with pd64.
+unsafe extern "C" fn store<T: PdClass>(object: *mut c_void, slot: PdFloat) {
+    let object = object as *mut Object<T>;
+    match to_slot(slot as f32) {
+        Some(slot) => controls(object).presets.store(slot, T::PARAMETERS),
+        None => log_error::<T>(object, &format!("invalid slot {}", slot)),
+    }
+}
+
+#[allow(clippy::unnecessary_cast)] // The cast is only needed with pd64.
+unsafe extern "C" fn recall<T: PdClass>(object: *mut c_void, slot: PdFloat) {
+    let object = object as *mut Object<T>;
+    let result = match to_slot(slot as f32) {
+        Some(slot) => controls(object)
+            .presets
+            .recall(slot, &mut (*object).state, T::PARAMETERS),
+        None => Err(format!("invalid slot {}", slot)),
+    };
+    if let Err(error) = result {
+        log_error::<T>(object, &error);
+    }
+}
+
+/// `morph <slot a> <slot b> <amount>` glides to values between two slots.
+unsafe extern "C" fn morph<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    let object = object as *mut Object<T>;
+    let arguments: Vec<_> = from_pd_atoms(argc, argv).iter().map(Atom::float).collect();
+    let result = match arguments[..] {
+        [Some(a), Some(b), Some(amount)] => match (to_slot(a), to_slot(b)) {
+            (Some(a), Some(b)) => {
+                controls(object)
+                    .presets
+                    .morph((a, b), amount, &mut (*object).state, T::PARAMETERS)
             }
+            _ => Err(format!("invalid slots {} and {}", a, b)),
+        },
+        _ => Err("morph expects two slots and an amount".to_string()),
+    };
+    if let Err(error) = result {
+        log_error::<T>(object, &error);
+    }
+}
 
-            $perform(&mut *receiver, number_of_frames, &inlets, &mut outlets);
+/// Controls of an object whose class registered methods working with them.
+unsafe fn controls<'a, T>(object: *mut Object<T>) -> &'a mut Controls {
+    (*object)
+        .controls
+        .as_mut()
+        .expect("controls exist for classes with parameters")
+}
 
-            buffer_pointer.add(buffer_length)
+/// `randomize [amount] [seed]` perturbs continuous parameters, fully by
+/// default.
+unsafe extern "C" fn randomize<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    let object = object as *mut Object<T>;
+    let arguments: Vec<_> = from_pd_atoms(argc, argv).iter().map(Atom::float).collect();
+    let (amount, seed) = match arguments[..] {
+        [] => (1.0, None),
+        [Some(amount)] => (amount, None),
+        [Some(amount), Some(seed)] => (amount, Some(seed as u64)),
+        _ => {
+            log_error::<T>(object, "randomize expects an amount and a seed");
+            return;
         }
     };
+    controls(object)
+        .presets
+        .randomize(amount, seed, &mut (*object).state, T::PARAMETERS);
+}
+
+unsafe extern "C" fn undo<T: PdClass>(object: *mut c_void) {
+    let object = object as *mut Object<T>;
+    if let Err(error) = controls(object)
+        .presets
+        .undo(&mut (*object).state, T::PARAMETERS)
+    {
+        log_error::<T>(object, &error);
+    }
+}
+
+unsafe extern "C" fn lock<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    set_lock::<T>(object as *mut Object<T>, &from_pd_atoms(argc, argv), true);
+}
+
+unsafe extern "C" fn unlock<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    set_lock::<T>(object as *mut Object<T>, &from_pd_atoms(argc, argv), false);
+}
+
+unsafe fn set_lock<T: PdClass>(object: *mut Object<T>, arguments: &[Atom], locked: bool) {
+    for argument in arguments {
+        let name = match argument {
+            Atom::Symbol(name) => name.to_string(),
+            Atom::Float(value) => value.to_string(),
+        };
+        match find_parameter::<T>(&name) {
+            Some(index) => controls(object).presets.lock(index, locked),
+            None => log_error::<T>(object, &format!("unknown parameter {}", name)),
+        }
+    }
+}
+
+/// `learn <parameter>` binds the next control change to the parameter.
+unsafe extern "C" fn learn<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    let object = object as *mut Object<T>;
+    match parameter_index::<T>(&from_pd_atoms(argc, argv)) {
+        Ok(index) => {
+            controls(object).midi.learn(index);
+            subscribe(object);
+        }
+        Err(error) => log_error::<T>(object, &error),
+    }
+}
+
+unsafe extern "C" fn unlearn<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    let object = object as *mut Object<T>;
+    match parameter_index::<T>(&from_pd_atoms(argc, argv)) {
+        Ok(index) => controls(object).midi.forget(index),
+        Err(error) => log_error::<T>(object, &error),
+    }
+}
+
+/// `map <parameter> <cc> <channel>` restores a learned mapping, as saved with
+/// the patch.
+unsafe extern "C" fn map<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    let object = object as *mut Object<T>;
+    let arguments = from_pd_atoms(argc, argv);
+    let result = match arguments[..] {
+        [name, Atom::Float(controller), Atom::Float(channel)] => {
+            parameter_index::<T>(&[name]).map(|index| (index, controller, channel))
+        }
+        _ => Err("map expects a parameter, controller and channel".to_string()),
+    };
+    match result {
+        Ok((index, controller, channel)) => {
+            controls(object)
+                .midi
+                .map(index, controller as u32, channel as u32);
+            subscribe(object);
+        }
+        Err(error) => log_error::<T>(object, &error),
+    }
+}
+
+/// `cc <value> <controller> [channel]` takes outlets of `[ctlin]` in order.
+unsafe extern "C" fn cc<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    receive_cc::<T>(object, &from_pd_atoms(argc, argv));
+}
+
+unsafe fn receive_cc<T: PdClass>(object: *mut c_void, arguments: &[Atom]) {
+    let object = object as *mut Object<T>;
+    let arguments: Vec<_> = arguments.iter().map(Atom::float).collect();
+    let (value, controller, channel) = match arguments[..] {
+        [Some(value), Some(controller)] => (value, controller, 1.0),
+        [Some(value), Some(controller), Some(channel)] => (value, controller, channel),
+        _ => {
+            log_error::<T>(object, "cc expects a value, controller and channel");
+            return;
+        }
+    };
+    let (controller, channel) = (controller as u32, channel as u32);
+
+    let midi = &mut controls(object).midi;
+    let learning = midi.is_learning();
+    let changes = midi.receive(controller, channel, value, T::PARAMETERS);
+    if learning {
+        if let Some(mapping) = midi.mappings().last() {
+            Logger::new(object as *const c_void).info(&format!(
+                "[{}] {} learned cc {} on channel {}",
+                T::NAME,
+                T::PARAMETERS[mapping.parameter].name,
+                controller,
+                channel
+            ));
+        }
+    }
+
+    // Changes go through the message handlers, so they are traced and kept
+    // in presets.
+    for (index, value) in changes {
+        (T::PARAMETERS[index].method)(object as *mut c_void, value as PdFloat);
+    }
+}
+
+unsafe fn subscribe<T: PdClass>(object: *mut Object<T>) {
+    if let Some(bindings) = &mut controls(object).bindings {
+        bindings.subscribe(receive_cc::<T>);
+    }
+}
+
+/// Save the object box the default way, followed by its learned mappings.
+unsafe extern "C" fn save<T: PdClass>(object: *mut pd_sys::t_gobj, binbuf: *mut pd_sys::t_binbuf) {
+    let default = SAVE_FUNCTIONS
+        .lock()
+        .unwrap()
+        .get(T::NAME)
+        .copied()
+        .flatten();
+    if let Some(default) = default {
+        default(object, binbuf);
+    }
+
+    let object = object as *mut Object<T>;
+    for mapping in controls(object).midi.mappings() {
+        let mut atoms: Vec<_> = [
+            Atom::Symbol("#A"),
+            Atom::Symbol("map"),
+            Atom::Symbol(T::PARAMETERS[mapping.parameter].name),
+            Atom::Float(mapping.controller as f32),
+            Atom::Float(mapping.channel as f32),
+        ]
+        .iter()
+        .map(to_pd_atom)
+        .collect();
+        pd_sys::binbuf_add(binbuf, atoms.len() as c_int, atoms.as_mut_ptr());
+        pd_sys::binbuf_addsemi(binbuf);
+    }
+}
+
+/// Start recording parameter changes, from the current values.
+unsafe extern "C" fn rec<T: PdClass>(object: *mut c_void) {
+    let object = object as *mut Object<T>;
+    let controls = controls(object);
+    let initial = controls.presets.snapshot(T::PARAMETERS);
+    controls.recorder.record(&initial);
+}
+
+unsafe extern "C" fn stop<T: PdClass>(object: *mut c_void) {
+    let object = object as *mut Object<T>;
+    controls(object).recorder.stop();
+}
+
+unsafe extern "C" fn play<T: PdClass>(object: *mut c_void) {
+    let object = object as *mut Object<T>;
+    controls(object).recorder.play(false);
+}
+
+unsafe extern "C" fn play_loop<T: PdClass>(object: *mut c_void) {
+    let object = object as *mut Object<T>;
+    controls(object).recorder.play(true);
+}
+
+/// `write <file>` saves the recording as a script of `automaton-render`.
+unsafe extern "C" fn write<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    let object = object as *mut Object<T>;
+    let controls = controls(object);
+    let result = file_path(&controls.directory, &from_pd_atoms(argc, argv)).and_then(|path| {
+        let script = controls
+            .recorder
+            .to_script(T::NAME, T::PARAMETERS, controls.sample_rate);
+        std::fs::write(&path, script)
+            .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    });
+    if let Err(error) = result {
+        log_error::<T>(object, &error);
+    }
+}
+
+/// `read <file>` loads a recording, messages of other instruments in the
+/// script are ignored.
+unsafe extern "C" fn read<T: PdClass>(
+    object: *mut c_void,
+    _selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    let object = object as *mut Object<T>;
+    let controls = controls(object);
+    let result = file_path(&controls.directory, &from_pd_atoms(argc, argv)).and_then(|path| {
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        Recorder::from_script(&text, T::NAME, find_parameter::<T>, controls.sample_rate)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    });
+    match result {
+        Ok(recorder) => controls.recorder = recorder,
+        Err(error) => log_error::<T>(object, &error),
+    }
+}
+
+fn file_path(directory: &Path, arguments: &[Atom]) -> Result<PathBuf, String> {
+    match arguments {
+        [Atom::Symbol(name)] => Ok(directory.join(name)),
+        _ => Err("expected a file name".to_string()),
+    }
+}
+
+fn parameter_index<T: PdClass>(arguments: &[Atom]) -> Result<usize, String> {
+    match arguments {
+        [Atom::Symbol(name)] => {
+            find_parameter::<T>(name).ok_or_else(|| format!("unknown parameter {}", name))
+        }
+        _ => Err("expected a parameter name".to_string()),
+    }
+}
+
+fn to_slot(value: f32) -> Option<u32> {
+    if value >= 0.0 && value.fract() == 0.0 {
+        Some(value as u32)
+    } else {
+        None
+    }
+}
+
+unsafe fn log_error<T: PdClass>(object: *mut Object<T>, message: &str) {
+    Logger::new(object as *const c_void).error(&format!("[{}] {}", T::NAME, message));
+}
+
+/// Let `debug 1` trace changes of parameters of the object.
+unsafe extern "C" fn set_debug<T: PdClass>(object: *mut c_void, value: PdFloat) {
+    let object = object as *mut Object<T>;
+    (*object).debug = value > 0.5;
+}
+
+unsafe fn new_signal_inlet(object: *mut pd_sys::t_object, fallback: Option<f32>) {
+    match fallback {
+        Some(value) => {
+            #[cfg(not(feature = "pd64"))]
+            pd_sys::signalinlet_new(object, value);
+            #[cfg(feature = "pd64")]
+            pd64::signalinlet_new(object, value as f64);
+        }
+        None => {
+            pd_sys::inlet_new(
+                object,
+                &mut (*object).te_g.g_pd,
+                &mut pd_sys::s_signal,
+                &mut pd_sys::s_signal,
+            );
+        }
+    }
+}
+
+#[doc(hidden)]
+#[allow(clippy::unnecessary_cast)] // The cast is only needed with pd64.
+pub unsafe fn call_parameter_method<T: PdClass>(
+    object: *mut c_void,
+    value: PdFloat,
+    name: &str,
+    method: fn(&mut T, f32),
+) {
+    let object = object as *mut Object<T>;
+    if (*object).debug {
+        Logger::new(object as *const c_void).debug(&format!("[{}] {} {}", T::NAME, name, value));
+    }
+    if let (Some(controls), Some(index)) = (&mut (*object).controls, find_parameter::<T>(name)) {
+        controls.presets.set(index, value as f32);
+        controls.recorder.capture(index, value as f32);
+    }
+    method(&mut (*object).state, value as f32);
+    if let Some(controls) = &(*object).controls {
+        update_choices::<T>(controls);
+    }
+}
+
+/// `<selector> <name>`, e.g. `bank soft`, sets the parameter of the choice
+/// to the value of the name. Floats are passed to the parameter as they are.
+unsafe extern "C" fn choose<T: PdClass>(
+    object: *mut c_void,
+    selector: *mut pd_sys::t_symbol,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+) {
+    let object = object as *mut Object<T>;
+    let selector = std::ffi::CStr::from_ptr((*selector).s_name).to_string_lossy();
+    let choice = T::CHOICES
+        .iter()
+        .find(|c| c.selector == selector)
+        .expect("method is registered for choices only");
+    let index = find_parameter::<T>(choice.parameter).expect("parameters are validated");
+    let parameter = &T::PARAMETERS[index];
+
+    let value = match from_pd_atoms(argc, argv)[..] {
+        [Atom::Symbol(name)] => choice.value(name, &parameter.range),
+        [Atom::Float(value)] => Some(value),
+        _ => None,
+    };
+    match value {
+        Some(value) => (parameter.method)(object as *mut c_void, value as PdFloat),
+        None => log_error::<T>(
+            object,
+            &format!(
+                "{} expects one of {}",
+                choice.selector,
+                choice.names.join(", ")
+            ),
+        ),
+    }
+}
+
+/// Indices of names of active values of all choices, `None` for parameters
+/// that were never set.
+fn active_choices<T: PdClass>(presets: &Presets) -> impl Iterator<Item = Option<usize>> + '_ {
+    T::CHOICES.iter().map(move |choice| {
+        let index = find_parameter::<T>(choice.parameter)?;
+        let value = presets.value(index)?;
+        Some(choice.index(value, &T::PARAMETERS[index].range))
+    })
+}
+
+/// Schedule a report of choices whose active names changed.
+fn update_choices<T: PdClass>(controls: &Controls) {
+    if let Some(choices) = &controls.choices {
+        choices.update(active_choices::<T>(&controls.presets));
+    }
+}
+
+unsafe extern "C" fn report_choices<T: PdClass>(object: *mut c_void) {
+    let object = object as *mut Object<T>;
+    if let Some(Controls {
+        presets,
+        choices: Some(choices),
+        ..
+    }) = &mut (*object).controls
+    {
+        choices.report(T::CHOICES, active_choices::<T>(presets));
+    }
+}
+
+#[doc(hidden)]
+pub unsafe fn call_bang_method<T>(object: *mut c_void, method: fn(&mut T)) {
+    let object = object as *mut Object<T>;
+    method(&mut (*object).state);
+}
+
+#[doc(hidden)]
+pub unsafe fn call_gimme_method<T>(
+    object: *mut c_void,
+    argc: c_int,
+    argv: *mut pd_sys::t_atom,
+    method: fn(&mut T, &[Atom]),
+) {
+    let object = object as *mut Object<T>;
+    method(&mut (*object).state, &from_pd_atoms(argc, argv));
+}
+
+unsafe extern "C" fn dsp<T: PdClass>(object: *mut Object<T>, signal: *mut *mut pd_sys::t_signal) {
+    let iolets = T::SIGNAL_INLETS + T::SIGNAL_OUTLETS;
+
+    let vector_length = {
+        let receiver = 1;
+        let number_of_frames = 1;
+        let channels = iolets;
+        receiver + number_of_frames + iolets + channels
+    };
+
+    let signal = std::slice::from_raw_parts_mut(signal, iolets);
+
+    let number_of_frames = (*signal[0]).s_n as usize;
+
+    let mut channels = [1; 2 * MAX_SIGNALS];
+    if T::MULTICHANNEL && multichannel_supported() {
+        let (inlet_channels, outlet_channels) = channels.split_at_mut(T::SIGNAL_INLETS);
+        for (i, channels) in inlet_channels.iter_mut().enumerate() {
+            *channels = (*(signal[i] as *mut MultichannelSignal)).s_nchans as usize;
+        }
+        // Outlets of multichannel classes are not allocated by Pure Data,
+        // they must be created even when they stay mono.
+        for (i, channels) in outlet_channels
+            .iter_mut()
+            .take(T::SIGNAL_OUTLETS)
+            .enumerate()
+        {
+            *channels = (*object)
+                .state
+                .signal_outlet_channels(i, inlet_channels)
+                .max(1);
+            set_multichannel_outlet(&mut signal[T::SIGNAL_INLETS + i], *channels);
+        }
+    }
+
+    (*object).scratch = channels[..iolets]
+        .iter()
+        .map(|channels| vec![0.0; number_of_frames * channels])
+        .collect();
+    if (*object).controls.is_some() {
+        (*object).parts = (*object).scratch.clone();
+    }
+
+    let vector_size = vector_length * std::mem::size_of::<*mut pd_sys::t_int>();
+    let vector_pointer = pd_sys::getbytes(vector_size);
+    assert!(
+        !vector_pointer.is_null(),
+        "null pointer from pd_sys::getbytes",
+    );
+
+    let vector = vector_pointer as *mut *mut pd_sys::t_int;
+    let vector: &mut [*mut pd_sys::t_int] = std::slice::from_raw_parts_mut(vector, vector_length);
+
+    vector[1] = number_of_frames as *mut pd_sys::t_int;
+    for i in 0..iolets {
+        vector[2 + i] = (*signal[i]).s_vec as *mut pd_sys::t_int;
+        vector[2 + iolets + i] = channels[i] as *mut pd_sys::t_int;
+    }
+
+    vector[0] = object as *mut pd_sys::t_int;
+
+    (*object).meter.reset();
+    (*object).deadline = Duration::from_secs_f32(number_of_frames as f32 / sample_rate());
+
+    pd_sys::dsp_addv(
+        Some(perform::<T>),
+        vector_length as c_int,
+        vector_pointer as *mut pd_sys::t_int,
+    );
+
+    pd_sys::freebytes(vector_pointer, vector_size);
+}
+
+#[allow(clippy::unnecessary_cast)] // The casts are only needed with pd64.
+unsafe extern "C" fn perform<T: PdClass>(buffer_pointer: *mut pd_sys::t_int) -> *mut pd_sys::t_int {
+    let iolets = T::SIGNAL_INLETS + T::SIGNAL_OUTLETS;
+
+    let buffer_length = {
+        let reserved = 1;
+        let receiver = 1;
+        let number_of_frames = 1;
+        let channels = iolets;
+        reserved + receiver + number_of_frames + iolets + channels
+    };
+
+    let arguments = std::slice::from_raw_parts(buffer_pointer, buffer_length);
+
+    let object = arguments[1] as *mut Object<T>;
+
+    let number_of_frames = arguments[2] as usize;
+
+    let start = Instant::now();
+
+    let mut channels = [1; 2 * MAX_SIGNALS];
+    for (i, channels) in channels.iter_mut().take(iolets).enumerate() {
+        *channels = arguments[3 + iolets + i] as usize;
+    }
+
+    let signal = |i: usize| read_signal(arguments[3 + i], number_of_frames * channels[i]);
+
+    // Pure Data may reuse input buffers for outputs. All inputs are copied
+    // before the object runs and outputs are only written once it is done.
+    // The copy also converts samples of double-precision Pure Data.
+    for (i, buffer) in (*object)
+        .scratch
+        .iter_mut()
+        .take(T::SIGNAL_INLETS)
+        .enumerate()
+    {
+        for (target, source) in buffer.iter_mut().zip(signal(i).iter()) {
+            *target = *source as f32;
+        }
+    }
+
+    if let Some(controls) = &(*object).controls {
+        let inbox = Arc::clone(&controls.inbox);
+        while let Some((index, value)) = inbox.pop() {
+            (T::PARAMETERS[index].method)(object as *mut c_void, value as PdFloat);
+        }
+    }
+
+    // Parameters may change within the block, e.g. while gliding or
+    // replaying a recording. The block is then processed in parts, with the
+    // changes applied between them.
+
+    let mut part_start = 0;
+    while part_start < number_of_frames {
+        while let Some((index, value)) = (*object)
+            .controls
+            .as_mut()
+            .and_then(|controls| controls.next_replayed(part_start))
+        {
+            (T::PARAMETERS[index].method)(object as *mut c_void, value as PdFloat);
+        }
+        let part_end = match &mut (*object).controls {
+            Some(controls) => controls.step(
+                &mut (*object).state,
+                T::PARAMETERS,
+                part_start,
+                number_of_frames,
+                (*object).deadline.as_secs_f32(),
+            ),
+            None => number_of_frames,
+        };
+        perform_part(
+            &mut *object,
+            number_of_frames,
+            part_start..part_end,
+            &channels[..iolets],
+        );
+        part_start = part_end;
+    }
+
+    for (i, buffer) in (*object).scratch.iter().skip(T::SIGNAL_INLETS).enumerate() {
+        for (target, source) in signal(T::SIGNAL_INLETS + i).iter_mut().zip(buffer.iter()) {
+            *target = *source as PdFloat;
+        }
+    }
+
+    if let Some(controls) = &mut (*object).controls {
+        controls.recorder.advance(number_of_frames);
+        update_choices::<T>(controls);
+    }
+    (*object).meter.record(start.elapsed(), (*object).deadline);
+
+    buffer_pointer.add(buffer_length)
+}
+
+/// Run the object over the given frames of the block held in the scratch
+/// buffers. Channels of a part have to be moved next to each other first.
+fn perform_part<T: PdClass>(
+    object: &mut Object<T>,
+    number_of_frames: usize,
+    frames: Range<usize>,
+    channels: &[usize],
+) {
+    let length = frames.len();
+    let (inlet_buffers, outlet_buffers) = object.scratch.split_at_mut(T::SIGNAL_INLETS);
+
+    if length == number_of_frames {
+        let mut inlets: [&[f32]; MAX_SIGNALS] = Default::default();
+        for (inlet, buffer) in inlets.iter_mut().zip(inlet_buffers.iter()) {
+            *inlet = &buffer[..];
+        }
+        let mut outlets: [&mut [f32]; MAX_SIGNALS] = Default::default();
+        for (outlet, buffer) in outlets.iter_mut().zip(outlet_buffers.iter_mut()) {
+            *outlet = &mut buffer[..];
+        }
+        object.state.perform(
+            number_of_frames,
+            &inlets[..T::SIGNAL_INLETS],
+            &mut outlets[..T::SIGNAL_OUTLETS],
+        );
+        return;
+    }
+
+    let (inlet_parts, outlet_parts) = object.parts.split_at_mut(T::SIGNAL_INLETS);
+
+    let mut inlets: [&[f32]; MAX_SIGNALS] = Default::default();
+    for (i, (inlet, part)) in inlets.iter_mut().zip(inlet_parts.iter_mut()).enumerate() {
+        for channel in 0..channels[i] {
+            let offset = channel * number_of_frames;
+            part[channel * length..(channel + 1) * length]
+                .copy_from_slice(&inlet_buffers[i][offset + frames.start..offset + frames.end]);
+        }
+        *inlet = &part[..length * channels[i]];
+    }
+
+    let mut outlets: [&mut [f32]; MAX_SIGNALS] = Default::default();
+    for (i, (outlet, part)) in outlets.iter_mut().zip(outlet_parts.iter_mut()).enumerate() {
+        *outlet = &mut part[..length * channels[T::SIGNAL_INLETS + i]];
+    }
+
+    object.state.perform(
+        length,
+        &inlets[..T::SIGNAL_INLETS],
+        &mut outlets[..T::SIGNAL_OUTLETS],
+    );
+
+    for (i, (buffer, part)) in outlet_buffers.iter_mut().zip(outlets.iter()).enumerate() {
+        for channel in 0..channels[T::SIGNAL_INLETS + i] {
+            let offset = channel * number_of_frames;
+            buffer[offset + frames.start..offset + frames.end]
+                .copy_from_slice(&part[channel * length..(channel + 1) * length]);
+        }
+    }
 }
 
 pub unsafe fn read_signal<'a>(
     pointer: pd_sys::t_int,
     number_of_frames: usize,
-) -> &'a mut [pd_sys::t_float] {
-    let samples = pointer as *mut pd_sys::t_sample;
+) -> &'a mut [PdFloat] {
+    let samples = pointer as *mut PdFloat;
     std::slice::from_raw_parts_mut(samples, number_of_frames)
 }
+
+// Not exposed by `pd_sys`, available since Pure Data 0.54.
+const CLASS_MULTICHANNEL: c_int = 0x400;
+
+type SetMultiout = unsafe extern "C" fn(*mut *mut pd_sys::t_signal, c_int);
+
+/// Leading fields of `t_signal` as laid out since Pure Data 0.54.
+#[repr(C)]
+struct MultichannelSignal {
+    s_length: c_int,
+    s_vec: *mut PdFloat,
+    s_sr: PdFloat,
+    s_nchans: c_int,
+}
+
+lazy_static! {
+    // Linking the symbol directly would prevent the library from loading in
+    // older versions of Pure Data.
+    static ref SIGNAL_SETMULTIOUT: Option<SetMultiout> = unsafe { find_signal_setmultiout() };
+}
+
+#[cfg(unix)]
+unsafe fn find_signal_setmultiout() -> Option<SetMultiout> {
+    let symbol = libc::dlsym(
+        libc::RTLD_DEFAULT,
+        cstr::cstr("signal_setmultiout").as_ptr(),
+    );
+    if symbol.is_null() {
+        None
+    } else {
+        Some(std::mem::transmute::<*mut c_void, SetMultiout>(symbol))
+    }
+}
+
+#[cfg(not(unix))]
+unsafe fn find_signal_setmultiout() -> Option<SetMultiout> {
+    None
+}
+
+fn multichannel_supported() -> bool {
+    SIGNAL_SETMULTIOUT.is_some()
+}
+
+unsafe fn set_multichannel_outlet(signal: *mut *mut pd_sys::t_signal, channels: usize) {
+    let set_multiout = SIGNAL_SETMULTIOUT.expect("multichannel must be supported");
+    set_multiout(signal, channels as c_int);
+}
+
+pub unsafe fn free_method<T>() -> pd_sys::t_method {
+    Some(std::mem::transmute::<
+        unsafe extern "C" fn(*mut T),
+        unsafe extern "C" fn(),
+    >(free::<T>))
+}
+
+/// Pure Data releases the object's memory on its own once this returns, we
+/// only need to run `Drop` on the Rust state living inside of it. Objects
+/// refused by their constructor are freed before they get registered, with
+/// no state to drop.
+unsafe extern "C" fn free<T>(object: *mut T) {
+    let created = {
+        let mut objects = OBJECTS.lock().unwrap();
+        let count = objects.len();
+        objects.retain(|info| info.address != object as usize);
+        objects.len() < count
+    };
+    if created {
+        std::ptr::drop_in_place(object);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+    struct Tracked {
+        _buffer: Vec<f32>,
+    }
+
+    impl Tracked {
+        fn new() -> Self {
+            LIVE.fetch_add(1, Ordering::SeqCst);
+            Self {
+                _buffer: vec![0.0; 48000],
+            }
+        }
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            LIVE.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[repr(C)]
+    struct Object {
+        _pd_obj: pd_sys::t_object,
+        _state: Tracked,
+    }
+
+    #[test]
+    fn free_method_drops_state_of_every_created_object() {
+        let free = unsafe { free_method::<Object>() }.unwrap();
+        let free = unsafe {
+            std::mem::transmute::<unsafe extern "C" fn(), unsafe extern "C" fn(*mut Object)>(free)
+        };
+
+        for _ in 0..1000 {
+            let object = Box::into_raw(Box::new(std::mem::MaybeUninit::<Object>::zeroed()));
+            unsafe {
+                let object = (*object).as_mut_ptr();
+                std::ptr::addr_of_mut!((*object)._state).write(Tracked::new());
+                OBJECTS.lock().unwrap().push(ObjectInfo {
+                    name: "tracked",
+                    sample_rate: 48000.0,
+                    meter: Arc::default(),
+                    inbox: None,
+                    find_parameter: |_| None,
+                    instance: 0,
+                    address: object as usize,
+                });
+                free(object);
+            }
+            // Pure Data would release the memory with `freebytes`, here we
+            // have to do it ourselves without running `Drop` again.
+            drop(unsafe { Box::from_raw(object) });
+        }
+
+        assert_eq!(LIVE.load(Ordering::SeqCst), 0);
+    }
+}
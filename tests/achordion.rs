@@ -0,0 +1,406 @@
+#![cfg(not(feature = "pd64"))]
+
+mod mock;
+
+use std::path::PathBuf;
+
+use mock::{Host, Message};
+
+const BLOCK: usize = 64;
+
+#[test]
+fn it_registers_trigger_inlet_and_four_outlets() {
+    let host = Host::new(48000.0);
+    let achordion = host.create("achordion~");
+
+    assert_eq!(achordion.signal_inlets(), 2);
+    assert_eq!(achordion.signal_outlets(), 4);
+}
+
+#[test]
+fn it_accepts_all_parameters() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+
+    for parameter in [
+        "solo",
+        "float",
+        "chord_degrees",
+        "scale_mode",
+        "scale_root",
+        "wavetable_bank",
+        "wavetable",
+        "detune",
+        "style",
+        "arp",
+        "arp_octaves",
+        "arp_solo",
+        "seed",
+    ] {
+        achordion.send_float(parameter, 0.5);
+    }
+
+    let outputs = achordion.process(&[], BLOCK);
+    assert!(outputs.iter().flatten().all(|x| x.is_finite()));
+}
+
+#[test]
+fn it_mixes_solo_and_chord_into_first_outlet() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+    achordion.send_float("float", 2.0);
+    achordion.send_float("solo", 3.0);
+    achordion.send_float("detune", 0.3);
+
+    for _ in 0..4 {
+        let outputs = achordion.process(&[], BLOCK);
+        for ((mix, solo), chord) in outputs[0].iter().zip(&outputs[1]).zip(&outputs[2]) {
+            assert!((mix - (solo + chord) / 2.0).abs() < f32::EPSILON);
+        }
+    }
+}
+
+#[test]
+fn it_releases_objects_repeatedly() {
+    let host = Host::new(44100.0);
+
+    for _ in 0..100 {
+        let mut achordion = host.create("achordion~");
+        achordion.process(&[], BLOCK);
+    }
+}
+
+#[test]
+fn it_fades_to_silence_when_bypassed_or_muted() {
+    let host = Host::new(48000.0);
+
+    for message in ["bypass", "mute"] {
+        let mut achordion = host.create("achordion~");
+        achordion.send_float("solo", 4.0);
+        let outputs = achordion.process(&[], BLOCK);
+        assert!(outputs[1].iter().any(|x| *x != 0.0));
+
+        achordion.send_float(message, 1.0);
+        let fade = achordion.process(&[], BLOCK * 4);
+        assert!(fade[1].iter().take(BLOCK).any(|x| *x != 0.0));
+        assert!(fade.iter().all(|o| o[BLOCK * 4 - 1] == 0.0));
+
+        achordion.send_float(message, 0.0);
+        let outputs = achordion.process(&[], BLOCK * 4);
+        assert!(outputs[1].iter().skip(BLOCK * 3).any(|x| *x != 0.0));
+    }
+}
+
+#[test]
+fn it_randomizes_parameters_and_undoes_it() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+    achordion.send_float("wavetable", 0.3);
+
+    achordion.send_message("lock detune float");
+    achordion.send_message("randomize 0.5 7");
+    achordion.send("undo");
+    achordion.send("undo");
+    achordion.send_message("lock tempo");
+    achordion.send_message("randomize half");
+
+    assert_eq!(
+        host.log(),
+        vec![
+            "error: [achordion~] there is nothing to undo".to_string(),
+            "error: [achordion~] unknown parameter tempo".to_string(),
+            "error: [achordion~] randomize expects an amount and a seed".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn it_replays_changes_at_the_frame_they_are_due() {
+    let host = Host::new(48000.0);
+    let path = std::env::temp_dir().join("automaton-achordion-take.txt");
+    std::fs::write(
+        &path,
+        format!(
+            "t=0 achordion solo 3\nt={} achordion solo 0\n",
+            10.0 / 48000.0
+        ),
+    )
+    .unwrap();
+
+    let mut replayed = host.create("achordion~");
+    replayed.send_message("read automaton-achordion-take.txt");
+    replayed.send("play");
+    let replayed = replayed.process(&[], BLOCK);
+    std::fs::remove_file(path).unwrap();
+
+    let mut reference = host.create("achordion~");
+    reference.send_float("solo", 3.0);
+    let reference = reference.process(&[], BLOCK);
+
+    assert_eq!(replayed[1][..10], reference[1][..10]);
+    assert!(reference[1][10..].iter().any(|x| *x != 0.0));
+    assert!(replayed[1][10..].iter().all(|x| *x == 0.0));
+}
+
+#[test]
+fn it_selects_scales_defined_by_the_user() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+
+    achordion.send_message("scale hirajoshi 0 2 3 7 8");
+    achordion.send_message("scale_mode_symbol hirajoshi");
+    achordion.send_float("solo", 3.3);
+    achordion.send_float("float", 2.0);
+    achordion.send_float("scale_root", 0.25);
+    achordion.send_message("scale_mode_symbol pelog");
+    achordion.send_message("scale broken 0 two");
+    achordion.send_float("scale_mode", 0.5);
+
+    let outputs = achordion.process(&[], BLOCK);
+    assert!(outputs.iter().flatten().all(|x| x.is_finite()));
+    assert_eq!(
+        host.log(),
+        vec![
+            "[achordion~] defined scale hirajoshi".to_string(),
+            "error: [achordion~] unknown scale pelog".to_string(),
+            "error: [achordion~] scale expects a name followed by semitones".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn it_loads_microtonal_tuning_relative_to_the_patch() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+
+    let mut scl = "! automaton-19edo.scl\n19 equal divisions\n 19\n".to_string();
+    for step in 1..=19 {
+        scl.push_str(&format!(" {:.5}\n", step as f32 * 1200.0 / 19.0));
+    }
+    let path = std::env::temp_dir().join("automaton-19edo.scl");
+    std::fs::write(&path, scl).unwrap();
+
+    achordion.send_message("tuning automaton-19edo.scl");
+    achordion.send_float("solo", 3.3);
+    achordion.send_message("tuning automaton-missing.scl");
+    let mut other = host.create("achordion~");
+    other.send_message("scale_mode_symbol automaton-19edo");
+    std::fs::remove_file(path).unwrap();
+
+    let log = host.log();
+    assert_eq!(
+        log[0],
+        "[achordion~] loaded tuning automaton-19edo with 19 steps"
+    );
+    assert!(log[1].starts_with("error: [achordion~] cannot read automaton-missing.scl"));
+    assert_eq!(log.len(), 2);
+}
+
+#[test]
+fn it_selects_bank_mode_and_style_by_name_and_reports_them() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+
+    achordion.send_message("bank soft");
+    achordion.send_message("mode dorian");
+    achordion.send_float("style", 0.5);
+    achordion.process(&[], BLOCK);
+    achordion.send_float("wavetable_bank", 0.3);
+    achordion.send_float("wavetable_bank", 0.4);
+    achordion.send_float("scale_mode", 0.2);
+    achordion.process(&[], BLOCK);
+    achordion.send_message("style arpeggio");
+
+    assert_eq!(
+        achordion.messages(0),
+        vec![
+            Message::Anything("bank soft".to_string()),
+            Message::Anything("mode dorian".to_string()),
+            Message::Anything("style fifths".to_string()),
+            Message::Anything("bank harsh".to_string()),
+        ]
+    );
+    assert_eq!(
+        host.log(),
+        vec!["error: [achordion~] style expects one of thirds, fifths, octaves".to_string()]
+    );
+}
+
+/// Number of cells of the radio of the patch editor sending the setting.
+fn patch_editor_cells(setting: &str) -> usize {
+    let patch = std::fs::read_to_string(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("puredata/patch_editor_abs/achordion.pd"),
+    )
+    .unwrap();
+    let sender = format!("achordion-{}-s", setting);
+    let radio = patch
+        .lines()
+        .find(|l| l.contains(" hradio ") && l.contains(&sender))
+        .unwrap();
+    radio.split_whitespace().nth(8).unwrap().parse().unwrap()
+}
+
+#[test]
+fn it_reports_names_of_cells_of_the_patch_editor() {
+    // Cells send their index divided by the number of cells, plus an offset
+    // set in the patch.
+    for (setting, parameter, offset, names) in [
+        (
+            "bank",
+            "wavetable_bank",
+            0.125,
+            &["perfect", "harsh", "soft", "sins"][..],
+        ),
+        (
+            "style",
+            "style",
+            0.166,
+            &["thirds", "fifths", "octaves"][..],
+        ),
+        (
+            "mode",
+            "scale_mode",
+            0.01,
+            &[
+                "ionian",
+                "dorian",
+                "phrygian",
+                "lydian",
+                "mixolydian",
+                "aeolian",
+                "locrian",
+                "chromatic",
+            ][..],
+        ),
+    ] {
+        let cells = patch_editor_cells(setting);
+        assert_eq!(cells, names.len(), "cells of {}", setting);
+
+        let host = Host::new(48000.0);
+        let mut achordion = host.create("achordion~");
+        for cell in 0..cells {
+            achordion.send_float(parameter, cell as f32 / cells as f32 + offset);
+            achordion.process(&[], BLOCK);
+        }
+        let expected: Vec<_> = names
+            .iter()
+            .map(|name| Message::Anything(format!("{} {}", setting, name)))
+            .collect();
+        assert_eq!(achordion.messages(0), expected);
+    }
+}
+
+#[test]
+fn it_arpeggiates_chord_tones_on_triggers() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+    achordion.send_float("float", 1.0);
+    achordion.send_float("chord_degrees", 0.4);
+    achordion.send_float("arp_octaves", 2.0);
+    achordion.send_message("arp updown");
+
+    let mut trigger = vec![0.0; BLOCK];
+    trigger[..8].fill(1.0);
+    let mut semitones = Vec::new();
+    for _ in 0..8 {
+        let outputs = achordion.process(&[&[], &trigger], BLOCK);
+        semitones.push(((outputs[3][BLOCK - 1] - 1.0) * 12.0).round());
+    }
+    assert_eq!(semitones, vec![0.0, 4.0, 7.0, 12.0, 16.0, 19.0, 16.0, 12.0]);
+
+    // Without triggers the last tone is held.
+    let outputs = achordion.process(&[], BLOCK);
+    assert!(outputs[3].iter().all(|x| (*x - 2.0).abs() < 1e-5));
+    assert_eq!(
+        achordion.messages(0),
+        vec![Message::Anything("arp updown".to_string())]
+    );
+}
+
+#[test]
+fn it_draws_waveform_into_array() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+    host.create_array("achordion-waveform", 128);
+
+    achordion.send_message("display achordion-waveform");
+    assert_eq!(host.array_redraws("achordion-waveform"), 1);
+    let waveform = host.array("achordion-waveform");
+    assert_eq!(waveform.len(), 128);
+    assert!(waveform.iter().all(|x| x.is_finite()));
+
+    // Without an interval it is drawn only once.
+    achordion.send_float("wavetable", 0.5);
+    achordion.process(&[], BLOCK * 16);
+    assert_eq!(host.array_redraws("achordion-waveform"), 1);
+
+    achordion.send_message("display achordion-missing");
+    achordion.send_message("display");
+    assert_eq!(
+        host.log(),
+        vec![
+            "error: [achordion~] array achordion-missing not found".to_string(),
+            "error: [achordion~] display expects an array and an optional interval in milliseconds"
+                .to_string(),
+        ]
+    );
+}
+
+#[test]
+fn it_draws_one_period_of_the_waveform_the_solo_plays() {
+    let host = Host::new(48000.0);
+    host.create_array("achordion-period", 128);
+    let mut displayed = host.create("achordion~");
+    let mut played = host.create("achordion~");
+    for achordion in [&mut displayed, &mut played] {
+        achordion.send_message("bank harsh");
+        achordion.send_float("wavetable", 0.4);
+    }
+
+    displayed.send_message("display achordion-period");
+    // The display plays C2, lasting 734 frames at 48 kHz.
+    played.send_float("solo", 2.0);
+    let solo = &played.process(&[], BLOCK * 12)[1];
+    let period = 48000.0 / (16.351_598 * 4.0);
+
+    for (i, drawn) in host.array("achordion-period").iter().enumerate() {
+        let position = i as f32 / 128.0 * period;
+        let (j, mix) = (position as usize, position.fract());
+        let expected = solo[j] + (solo[j + 1] - solo[j]) * mix;
+        assert!(
+            (drawn - expected).abs() < 1e-4,
+            "{} drawn at {} of the period, {} played",
+            drawn,
+            i,
+            expected
+        );
+    }
+}
+
+#[test]
+fn it_refreshes_waveform_display_once_it_changes() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+    host.create_array("achordion-live", 64);
+
+    achordion.send_message("display achordion-live 10");
+    assert_eq!(host.array_redraws("achordion-live"), 1);
+
+    // 64 frames at 48 kHz last 1.33 ms.
+    achordion.process(&[], BLOCK * 10);
+    assert_eq!(host.array_redraws("achordion-live"), 1);
+
+    achordion.send_message("bank sins");
+    achordion.process(&[], BLOCK * 10);
+    assert_eq!(host.array_redraws("achordion-live"), 2);
+
+    achordion.send_float("wavetable", 0.3);
+    achordion.send_float("wavetable", 0.6);
+    achordion.process(&[], BLOCK * 10);
+    assert_eq!(host.array_redraws("achordion-live"), 3);
+
+    achordion.send_message("display achordion-live 0");
+    achordion.send_float("wavetable", 0.9);
+    achordion.process(&[], BLOCK * 10);
+    assert_eq!(host.array_redraws("achordion-live"), 4);
+}
@@ -0,0 +1,87 @@
+#![cfg(not(feature = "pd64"))]
+
+mod mock;
+
+use mock::{Host, Message};
+
+const BLOCK: usize = 64;
+
+fn semitones(voct: f32) -> f32 {
+    (voct * 12.0 * 1000.0).round() / 1000.0
+}
+
+#[test]
+fn it_registers_root_inlet_and_two_outlets() {
+    let host = Host::new(48000.0);
+    let quantizer = host.create("achordion-quantizer~");
+
+    assert_eq!(quantizer.signal_inlets(), 2);
+    assert_eq!(quantizer.signal_outlets(), 2);
+}
+
+#[test]
+fn it_quantizes_to_scale_and_triggers_on_note_change() {
+    let host = Host::new(48000.0);
+    let mut quantizer = host.create("achordion-quantizer~");
+
+    let outputs = quantizer.process(&[&[3.9 / 12.0; BLOCK]], BLOCK);
+    assert!(outputs[0].iter().all(|x| semitones(*x) == 4.0));
+    assert_eq!(outputs[1][0], 1.0);
+
+    // The trigger lasts 5 ms and is not repeated while the note holds.
+    let outputs = quantizer.process(&[&[4.1 / 12.0; BLOCK * 4]], BLOCK * 4);
+    assert!(outputs[1][BLOCK * 3..].iter().all(|x| *x == 0.0));
+
+    quantizer.send_message("mode aeolian");
+    let outputs = quantizer.process(&[&[3.9 / 12.0; BLOCK]], BLOCK);
+    assert!(outputs[0].iter().all(|x| semitones(*x) == 3.0));
+    assert_eq!(outputs[1][0], 1.0);
+    assert_eq!(
+        quantizer.messages(0),
+        vec![Message::Anything("mode aeolian".to_string())]
+    );
+}
+
+#[test]
+fn it_quantizes_to_tones_of_chord_on_given_root() {
+    let host = Host::new(48000.0);
+    let mut quantizer = host.create("achordion-quantizer~");
+    quantizer.send_message("quantize chord");
+    quantizer.send_float("chord_degrees", 0.4);
+
+    // C major triad on C, then D minor one on D.
+    for (root, input, output) in [(0.0, 2.6, 4.0), (0.0, 6.0, 7.0), (2.0, 3.6, 5.0)] {
+        let outputs = quantizer.process(&[&[input / 12.0; BLOCK], &[root / 12.0; BLOCK]], BLOCK);
+        assert_eq!(semitones(outputs[0][BLOCK - 1]), output);
+    }
+}
+
+#[test]
+fn it_follows_scales_defined_through_achordion() {
+    let host = Host::new(48000.0);
+    let mut achordion = host.create("achordion~");
+    let mut quantizer = host.create("achordion-quantizer~");
+
+    achordion.send_message("scale quantizer-hirajoshi 0 2 3 7 8");
+    quantizer.send_message("scale_mode_symbol quantizer-hirajoshi");
+
+    let outputs = quantizer.process(&[&[4.9 / 12.0; BLOCK]], BLOCK);
+    assert_eq!(semitones(outputs[0][0]), 3.0);
+
+    quantizer.send_message("scale_mode_symbol missing");
+    assert!(host
+        .log()
+        .iter()
+        .any(|l| l.contains("[achordion-quantizer~] unknown scale missing")));
+}
+
+#[test]
+fn it_passes_input_through_when_bypassed() {
+    let host = Host::new(48000.0);
+    let mut quantizer = host.create("achordion-quantizer~");
+    quantizer.send_float("bypass", 1.0);
+
+    let outputs = quantizer.process(&[&[0.123; BLOCK]], BLOCK);
+    assert!(outputs[0].iter().all(|x| *x == 0.123));
+    assert!(outputs[1].iter().all(|x| *x == 0.0));
+}
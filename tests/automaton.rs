@@ -0,0 +1,104 @@
+#![cfg(not(feature = "pd64"))]
+
+mod mock;
+
+use mock::{Host, Message};
+
+#[test]
+fn it_reports_version() {
+    let host = Host::new(48000.0);
+    let mut automaton = host.create("automaton");
+
+    automaton.send("version");
+
+    assert_eq!(
+        automaton.messages(0),
+        vec![Message::Anything(format!(
+            "version {}",
+            env!("CARGO_PKG_VERSION")
+        ))]
+    );
+    assert!(host.log().iter().any(|l| l.contains("version")));
+}
+
+#[test]
+fn it_lists_registered_modules() {
+    let host = Host::new(48000.0);
+    let mut automaton = host.create("automaton");
+
+    automaton.send("modules");
+
+    let messages = automaton.messages(0);
+    for module in ["achordion~", "kaseta~"] {
+        assert!(messages.iter().any(|m| matches!(
+            m,
+            Message::Anything(m) if m.starts_with(&format!("modules {} ", module))
+        )));
+    }
+}
+
+#[test]
+fn it_reports_tape_memory_usage() {
+    let host = Host::new(48000.0);
+    let mut automaton = host.create("automaton");
+    let _kaseta = host.create("kaseta~");
+
+    automaton.send("memory");
+
+    let messages = automaton.messages(0);
+    let Message::Anything(message) = &messages[0] else {
+        panic!("unexpected message {:?}", messages[0]);
+    };
+    let values: Vec<f32> = message
+        .split(' ')
+        .skip(1)
+        .map(|v| v.parse().unwrap())
+        .collect();
+    assert!(values[0] > 0.0);
+    assert!(values[0] <= values[1]);
+}
+
+#[test]
+fn it_reports_sample_rate_of_instruments() {
+    let host = Host::new(44100.0);
+    let mut automaton = host.create("automaton");
+    let _achordion = host.create("achordion~");
+
+    automaton.send("sr");
+
+    assert!(automaton
+        .messages(0)
+        .contains(&Message::Anything("sr achordion~ 44100".to_string())));
+}
+
+#[test]
+fn it_reports_invalid_arguments_as_errors() {
+    let host = Host::new(48000.0);
+    let mut automaton = host.create("automaton");
+
+    automaton.send_message("pool -1");
+
+    assert!(host
+        .log()
+        .contains(&"error: [automaton] pool size must be positive".to_string()));
+}
+
+#[test]
+fn it_reports_dsp_load_of_instruments() {
+    let host = Host::new(48000.0);
+    let mut automaton = host.create("automaton");
+    let mut achordion = host.create("achordion~");
+    achordion.process(&[], 64);
+
+    automaton.send("cpu");
+    achordion.send("cpu");
+
+    assert!(automaton.messages(0).iter().any(|m| matches!(
+        m,
+        Message::Anything(m) if m.starts_with("cpu achordion~ ")
+    )));
+    assert!(host
+        .log()
+        .iter()
+        .any(|l| l.starts_with("[achordion~] cpu: ")));
+}
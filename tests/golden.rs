@@ -0,0 +1,174 @@
+//! Regression tests comparing rendered audio against stored references.
+//!
+//! References live in `tests/golden`. After an intentional change of sound,
+//! for example when bumping `achordion-lib`, `kaseta-dsp` or `sirena`,
+//! re-bless them with `cargo make bless` and listen to the result before
+//! committing.
+
+#![cfg(not(feature = "pd64"))]
+
+mod mock;
+
+use std::path::{Path, PathBuf};
+
+use mock::Host;
+
+const SAMPLE_RATE: u32 = 48000;
+const BLOCK: usize = 64;
+const TOLERANCE: f32 = 1e-4;
+
+#[test]
+fn achordion_chord_sweep_across_wavetable_banks() {
+    const BANKS: usize = 4;
+    const CHORDS: usize = 8;
+    const BLOCKS_PER_CHORD: usize = 16;
+
+    let host = Host::new(SAMPLE_RATE as f32);
+    let mut achordion = host.create("achordion~");
+    achordion.send_float("wavetable", 0.3);
+    achordion.send_float("detune", 0.1);
+    achordion.send_float("solo", 4.0);
+
+    let mut solo = Vec::new();
+    let mut chord = Vec::new();
+    for bank in 0..BANKS {
+        achordion.send_float("wavetable_bank", (bank as f32 + 0.5) / BANKS as f32);
+        for step in 0..CHORDS {
+            achordion.send_float("float", 1.0 + step as f32 / CHORDS as f32);
+            achordion.send_float("chord_degrees", step as f32 / CHORDS as f32);
+            for _ in 0..BLOCKS_PER_CHORD {
+                let outputs = achordion.process(&[], BLOCK);
+                solo.extend_from_slice(&outputs[1]);
+                chord.extend_from_slice(&outputs[2]);
+            }
+        }
+    }
+
+    check("achordion_chord_sweep", &[solo, chord]);
+}
+
+#[test]
+fn kaseta_head_feedback_sequence() {
+    const STEPS: usize = 6;
+    const BLOCKS_PER_STEP: usize = 64;
+
+    let host = Host::new(SAMPLE_RATE as f32);
+    let mut kaseta = host.create("kaseta~");
+    kaseta.send_float("seed", 42.0);
+    kaseta.send_float("dry_wet", 0.7);
+    kaseta.send_float("speed", 0.4);
+    kaseta.send_float("head_1_volume", 1.0);
+    kaseta.send_float("head_2_volume", 0.6);
+
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    let mut phase = 0;
+    for step in 0..STEPS {
+        let amount = step as f32 / (STEPS - 1) as f32;
+        kaseta.send_float("head_1_position", amount);
+        kaseta.send_float("head_1_feedback", 1.0 - amount);
+        kaseta.send_float("head_2_position", 1.0 - amount);
+        kaseta.send_float("head_2_feedback", amount * 0.8);
+        for _ in 0..BLOCKS_PER_STEP {
+            kaseta.send_bang();
+            let input = burst(&mut phase);
+            let outputs = kaseta.process(&[&input, &input], BLOCK);
+            left.extend_from_slice(&outputs[0]);
+            right.extend_from_slice(&outputs[1]);
+        }
+    }
+
+    check("kaseta_head_feedback", &[left, right]);
+}
+
+/// Short sine bursts separated by silence, giving the heads something to
+/// repeat.
+fn burst(phase: &mut usize) -> Vec<f32> {
+    const PERIOD: usize = SAMPLE_RATE as usize / 4;
+    const LENGTH: usize = SAMPLE_RATE as usize / 50;
+
+    (0..BLOCK)
+        .map(|_| {
+            let i = *phase % PERIOD;
+            *phase += 1;
+            if i < LENGTH {
+                (i as f32 * 440.0 * std::f32::consts::TAU / SAMPLE_RATE as f32).sin() * 0.5
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+fn check(name: &str, channels: &[Vec<f32>]) {
+    let path = reference_path(name);
+
+    if std::env::var_os("AUTOMATON_BLESS").is_some() {
+        write_wav(&path, channels);
+        return;
+    }
+
+    assert!(
+        path.exists(),
+        "reference {} is missing, create it with `cargo make bless`",
+        path.display()
+    );
+
+    let reference = read_wav(&path);
+    assert_eq!(
+        reference.len(),
+        channels.len(),
+        "number of channels differs"
+    );
+    for (channel, (rendered, reference)) in channels.iter().zip(reference.iter()).enumerate() {
+        assert_eq!(rendered.len(), reference.len(), "length differs");
+        let deviation = rendered
+            .iter()
+            .zip(reference.iter())
+            .map(|(a, b)| (a - b).abs())
+            .enumerate()
+            .fold((0, 0.0), |max, (i, x)| if x > max.1 { (i, x) } else { max });
+        assert!(
+            deviation.1 <= TOLERANCE,
+            "{} channel {} deviates by {} at frame {}, re-bless with `cargo make bless` if the change is intentional",
+            name,
+            channel,
+            deviation.1,
+            deviation.0
+        );
+    }
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.wav", name))
+}
+
+fn write_wav(path: &Path, channels: &[Vec<f32>]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let spec = hound::WavSpec {
+        channels: channels.len() as u16,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for i in 0..channels[0].len() {
+        for channel in channels {
+            writer.write_sample(channel[i]).unwrap();
+        }
+    }
+    writer.finalize().unwrap();
+}
+
+fn read_wav(path: &Path) -> Vec<Vec<f32>> {
+    let mut reader = hound::WavReader::open(path).unwrap();
+    let number_of_channels = reader.spec().channels as usize;
+    let mut channels = vec![Vec::new(); number_of_channels];
+    for (i, sample) in reader.samples::<f32>().enumerate() {
+        channels[i % number_of_channels].push(sample.unwrap());
+    }
+    channels
+}
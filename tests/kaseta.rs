@@ -0,0 +1,224 @@
+#![cfg(not(feature = "pd64"))]
+
+mod mock;
+
+use mock::Host;
+
+const BLOCK: usize = 64;
+
+/// Tests run in parallel and share the pool of tape memory, so it is made
+/// larger than the default before the first tape gets created.
+fn host() -> Host {
+    let host = Host::new(48000.0);
+    host.create("automaton 512");
+    host.clear_log();
+    host
+}
+
+#[test]
+fn it_registers_stereo_inlets_and_all_outlets() {
+    let host = host();
+    let kaseta = host.create("kaseta~");
+
+    assert_eq!(kaseta.signal_inlets(), 2);
+    assert_eq!(kaseta.signal_outlets(), 12);
+}
+
+#[test]
+fn it_processes_input_after_parameter_changes() {
+    let host = host();
+    let mut kaseta = host.create("kaseta~");
+    kaseta.send_float("dry_wet", 0.5);
+    kaseta.send_float("head_1_feedback", 0.8);
+    kaseta.send_float("switch_1", 1.0);
+    kaseta.send_bang();
+
+    let left: Vec<f32> = (0..BLOCK).map(|i| (i as f32 * 0.1).sin()).collect();
+    let right: Vec<f32> = (0..BLOCK).map(|i| (i as f32 * 0.2).sin()).collect();
+
+    for _ in 0..4 {
+        let outputs = kaseta.process(&[&left, &right], BLOCK);
+        assert_eq!(outputs.len(), 12);
+        assert!(outputs[..2].iter().flatten().all(|x| x.is_finite()));
+    }
+}
+
+#[test]
+fn it_outputs_leds_and_impulse_as_gates() {
+    let host = host();
+    let mut kaseta = host.create("kaseta~");
+    kaseta.send_bang();
+
+    let outputs = kaseta.process(&[], BLOCK);
+    for outlet in &outputs[2..] {
+        assert!(outlet.iter().all(|x| *x == 0.0 || *x == 1.0));
+    }
+}
+
+#[test]
+fn it_releases_objects_repeatedly() {
+    let host = host();
+
+    for _ in 0..20 {
+        let mut kaseta = host.create("kaseta~");
+        kaseta.process(&[], BLOCK);
+    }
+}
+
+#[test]
+fn it_traces_parameter_changes_in_debug_mode() {
+    let host = host();
+    let mut kaseta = host.create("kaseta~");
+
+    kaseta.send_float("dry_wet", 0.25);
+    kaseta.send_float("debug", 1.0);
+    kaseta.send_float("dry_wet", 0.5);
+    kaseta.send_float("debug", 0.0);
+    kaseta.send_float("dry_wet", 0.75);
+
+    let traces: Vec<_> = host
+        .log()
+        .into_iter()
+        .filter(|l| l.contains("dry_wet"))
+        .collect();
+    assert_eq!(traces, vec!["debug: [kaseta~] dry_wet 0.5".to_string()]);
+}
+
+#[test]
+fn it_passes_dry_input_when_bypassed() {
+    let host = host();
+    let mut kaseta = host.create("kaseta~");
+    let input: Vec<f32> = (0..BLOCK * 8).map(|i| (i as f32 * 0.1).sin()).collect();
+
+    kaseta.send_float("bypass", 1.0);
+    kaseta.process(&[&input, &input], BLOCK * 8);
+    let outputs = kaseta.process(&[&input, &input], BLOCK * 8);
+
+    assert_eq!(outputs[0], input);
+    assert_eq!(outputs[1], input);
+}
+
+#[test]
+fn it_fades_to_silence_when_muted() {
+    let host = host();
+    let mut kaseta = host.create("kaseta~");
+    let input = vec![1.0; BLOCK * 8];
+
+    kaseta.send_float("mute", 1.0);
+    kaseta.process(&[&input, &input], BLOCK * 8);
+    let outputs = kaseta.process(&[&input, &input], BLOCK * 8);
+
+    assert!(outputs[..2].iter().flatten().all(|x| *x == 0.0));
+}
+
+#[test]
+fn it_reports_recall_of_empty_slots() {
+    let host = host();
+    let mut kaseta = host.create("kaseta~");
+
+    kaseta.send_float("dry_wet", 0.5);
+    kaseta.send_float("store", 1.0);
+    kaseta.send_float("recall", 1.0);
+    kaseta.send_float("recall", 2.0);
+    kaseta.send_message("morph 1 3 0.5");
+    kaseta.send_message("morph 1 2");
+
+    assert_eq!(
+        host.log(),
+        vec![
+            "error: [kaseta~] slot 2 is empty".to_string(),
+            "error: [kaseta~] slot 3 is empty".to_string(),
+            "error: [kaseta~] morph expects two slots and an amount".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn it_learns_control_changes_and_saves_them_with_the_patch() {
+    let host = host();
+    let mut kaseta = host.create("kaseta~");
+
+    kaseta.send_float("debug", 1.0);
+    kaseta.send_message("learn dry_wet");
+    host.send_list("#ctlin", &[7.0, 127.0, 2.0]);
+    host.send_list("#ctlin", &[7.0, 0.0, 2.0]);
+    host.send_list("#ctlin", &[7.0, 0.0, 3.0]);
+    kaseta.send_message("learn control_1");
+    kaseta.send_message("cc 127 8");
+
+    assert_eq!(
+        host.log(),
+        vec![
+            "[kaseta~] dry_wet learned cc 7 on channel 2".to_string(),
+            "debug: [kaseta~] dry_wet 1".to_string(),
+            "debug: [kaseta~] dry_wet 0".to_string(),
+            "[kaseta~] control_1 learned cc 8 on channel 1".to_string(),
+            "debug: [kaseta~] control_1 5".to_string(),
+        ]
+    );
+    assert_eq!(
+        kaseta.save(),
+        "#X obj 0 0 kaseta~; #A map dry_wet 7 2; #A map control_1 8 1;"
+    );
+}
+
+#[test]
+fn it_restores_mappings_following_the_newest_object_until_loaded() {
+    let host = host();
+    let _previous = host.create("kaseta~");
+    let mut restored = host.create("kaseta~");
+    assert_eq!(host.receiver("#A"), Some(restored.as_pd()));
+
+    restored.send_float("debug", 1.0);
+    host.send_message("#A", "map head_1_pan 9 1");
+    restored.process(&[], BLOCK);
+    assert_eq!(host.receiver("#A"), None);
+
+    host.send_list("#ctlin", &[9.0, 127.0, 1.0]);
+    assert!(host
+        .log()
+        .contains(&"debug: [kaseta~] head_1_pan 1".to_string()));
+}
+
+#[test]
+fn it_replays_recorded_changes_from_a_file() {
+    let host = host();
+    let mut kaseta = host.create("kaseta~");
+
+    kaseta.send("rec");
+    kaseta.process(&[], BLOCK);
+    kaseta.send_float("dry_wet", 0.5);
+    kaseta.process(&[], BLOCK);
+    kaseta.process(&[], BLOCK);
+    kaseta.send_float("tone", 0.25);
+    kaseta.process(&[], BLOCK);
+    kaseta.send("stop");
+    kaseta.send_message("write automaton-kaseta-take.txt");
+
+    let path = std::env::temp_dir().join("automaton-kaseta-take.txt");
+    let script = std::fs::read_to_string(&path).unwrap();
+    assert!(script.contains(" kaseta dry_wet 0.5\n"));
+    assert!(script.contains(" kaseta tone 0.25\n"));
+
+    let mut replayed = host.create("kaseta~");
+    replayed.send_message("read automaton-kaseta-take.txt");
+    replayed.send_float("debug", 1.0);
+    replayed.send("play");
+    let mut traces = Vec::new();
+    for block in 0..8 {
+        let before = host.log().len();
+        replayed.process(&[], BLOCK);
+        for line in &host.log()[before..] {
+            traces.push((block, line.clone()));
+        }
+    }
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(
+        traces,
+        vec![
+            (1, "debug: [kaseta~] dry_wet 0.5".to_string()),
+            (3, "debug: [kaseta~] tone 0.25".to_string()),
+        ]
+    );
+}
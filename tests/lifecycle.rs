@@ -0,0 +1,70 @@
+#![cfg(not(feature = "pd64"))]
+
+//! Memory of the whole process is counted here, so the life cycle of objects
+//! is exercised by a single test.
+
+mod mock;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use mock::{Host, Message};
+
+struct Counting;
+
+static LIVE: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size() as isize, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+        System.dealloc(pointer, layout);
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        LIVE.fetch_add(size as isize - layout.size() as isize, Ordering::SeqCst);
+        System.realloc(pointer, layout, size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const BLOCK: usize = 64;
+
+fn pool_usage(automaton: &mut mock::Object) -> String {
+    automaton.send_message("pool");
+    let Message::Anything(report) = automaton.messages(0).pop().unwrap() else {
+        panic!("pool must be reported with a message");
+    };
+    report
+}
+
+#[test]
+fn it_returns_all_memory_of_deleted_objects() {
+    let host = Host::new(8000.0);
+    let mut automaton = host.create("automaton");
+
+    let cycle = || {
+        for name in ["kaseta~", "achordion~", "achordion-quantizer~"] {
+            host.create(name).process(&[], BLOCK);
+        }
+    };
+
+    // The first objects allocate the pool and lazily initialized tables.
+    cycle();
+    let pool = pool_usage(&mut automaton);
+    assert!(pool.starts_with("pool 0 0 "));
+    let live = LIVE.load(Ordering::SeqCst);
+
+    for _ in 0..100 {
+        cycle();
+    }
+
+    assert_eq!(LIVE.load(Ordering::SeqCst), live);
+    assert_eq!(pool_usage(&mut automaton), pool);
+}
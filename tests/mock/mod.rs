@@ -0,0 +1,873 @@
+//! Headless stand-in for the parts of Pure Data used by the externals.
+//!
+//! The functions below are linked in place of the symbols that would be
+//! provided by a running Pure Data. Classes registered by `automaton_setup`
+//! are kept globally, while sample rate, created objects and the DSP chain are
+//! local to the thread, so tests can run in parallel.
+//!
+//! Variadic functions of Pure Data are defined with the fixed arguments the
+//! library passes them. That matches the calling convention of the Linux
+//! targets.
+
+#![allow(dead_code)]
+
+use std::alloc::{self, Layout};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::{Mutex, Once};
+
+use pd_sys::{
+    t_atom, t_atomtype, t_binbuf, t_class, t_garray, t_gobj, t_inlet, t_int, t_object, t_outlet,
+    t_pd, t_signal, t_symbol, t_word,
+};
+
+type Method = unsafe extern "C" fn();
+type NewMethod = unsafe extern "C" fn() -> *mut c_void;
+type GimmeNewMethod = unsafe extern "C" fn(*mut t_symbol, c_int, *mut t_atom) -> *mut c_void;
+type FloatMethod = unsafe extern "C" fn(*mut c_void, f32);
+type BangMethod = unsafe extern "C" fn(*mut c_void);
+type GimmeMethod = unsafe extern "C" fn(*mut c_void, *mut t_symbol, c_int, *mut t_atom);
+type DspMethod = unsafe extern "C" fn(*mut c_void, *mut *mut t_signal);
+type SaveMethod = unsafe extern "C" fn(*mut t_gobj, *mut t_binbuf);
+type PerformRoutine = unsafe extern "C" fn(*mut t_int) -> *mut t_int;
+
+const ALIGNMENT: usize = 16;
+
+struct MockClass {
+    name: String,
+    new: Option<NewMethod>,
+    new_arguments: t_atomtype::Type,
+    free: Option<Method>,
+    size: usize,
+    signal_inlet: bool,
+    methods: HashMap<String, (Method, t_atomtype::Type)>,
+    bang: Option<Method>,
+    list: Option<Method>,
+    save: Option<SaveMethod>,
+}
+
+/// Binbufs hold atoms as text, with `;` ending each message.
+#[derive(Default)]
+struct MockBinbuf {
+    words: Vec<String>,
+}
+
+#[derive(Default)]
+struct Iolets {
+    signal_inlets: usize,
+    signal_outlets: usize,
+    outlets: Vec<*mut MockOutlet>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Bang,
+    Float(f32),
+    /// Selector followed by arguments, formatted the way `[print]` would.
+    Anything(String),
+}
+
+/// Clocks fire once the logical time passes their deadline. The time moves
+/// forward with every processed block.
+struct MockClock {
+    owner: *mut c_void,
+    method: BangMethod,
+    deadline: Option<f64>,
+}
+
+/// Arrays are found by their names, the way garrays bound to a symbol are.
+#[derive(Default)]
+struct MockArray {
+    words: Vec<t_word>,
+    redraws: usize,
+}
+
+pub struct MockOutlet {
+    signal: bool,
+    messages: Vec<Message>,
+}
+
+lazy_static::lazy_static! {
+    static ref CLASSES: Mutex<HashMap<String, usize>> = Mutex::new(HashMap::new());
+    static ref SYMBOLS: Mutex<HashMap<String, usize>> = Mutex::new(HashMap::new());
+}
+
+static SETUP: Once = Once::new();
+
+// Symbols starting with `#` are used by Pure Data to bind receivers, such as
+// `#A` or `#ctlin`. They are kept local to the thread, together with the
+// receivers bound to any symbol, so tests running in parallel do not see
+// each other's bindings.
+thread_local! {
+    static SAMPLE_RATE: Cell<f32> = const { Cell::new(48000.0) };
+    static IOLETS: RefCell<HashMap<usize, Iolets>> = RefCell::new(HashMap::new());
+    static DSP_CHAIN: RefCell<Vec<Vec<t_int>>> = const { RefCell::new(Vec::new()) };
+    static LOG: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static BINDINGS: RefCell<HashMap<String, Vec<usize>>> = RefCell::new(HashMap::new());
+    static LOCAL_SYMBOLS: RefCell<HashMap<String, usize>> = RefCell::new(HashMap::new());
+    static CLOCKS: RefCell<Vec<*mut MockClock>> = const { RefCell::new(Vec::new()) };
+    static TIME: Cell<f64> = const { Cell::new(0.0) };
+    static ARRAYS: RefCell<HashMap<String, Box<MockArray>>> = RefCell::new(HashMap::new());
+}
+
+pub struct Host;
+
+impl Host {
+    pub fn new(sample_rate: f32) -> Self {
+        SAMPLE_RATE.with(|s| s.set(sample_rate));
+        SETUP.call_once(|| unsafe { automaton::automaton_setup() });
+        Self
+    }
+
+    /// Create an object the way Pure Data would from the text of its box,
+    /// e.g. `automaton 64`.
+    pub fn create(&self, text: &str) -> Object {
+        self.try_create(text)
+            .unwrap_or_else(|| panic!("constructor of {} failed", text))
+    }
+
+    /// Like `create`, but returns nothing when the constructor refuses to
+    /// create the object.
+    pub fn try_create(&self, text: &str) -> Option<Object> {
+        let (name, mut arguments) = parse_message(text);
+        let class = CLASSES
+            .lock()
+            .unwrap()
+            .get(&name)
+            .copied()
+            .unwrap_or_else(|| panic!("class {} is not registered", name))
+            as *mut MockClass;
+
+        let constructor = unsafe { (*class).new }.expect("class has no constructor");
+        let pointer = unsafe {
+            match (*class).new_arguments {
+                t_atomtype::A_GIMME => {
+                    std::mem::transmute::<NewMethod, GimmeNewMethod>(constructor)(
+                        gensym(CString::new(name.as_str()).unwrap().as_ptr()),
+                        arguments.len() as c_int,
+                        arguments.as_mut_ptr(),
+                    )
+                }
+                _ => constructor(),
+            }
+        } as *mut t_object;
+        if pointer.is_null() {
+            return None;
+        }
+        unsafe {
+            (*pointer).te_binbuf = Box::into_raw(Box::new(MockBinbuf {
+                words: text.split_whitespace().map(str::to_owned).collect(),
+            })) as *mut t_binbuf;
+        }
+
+        let iolets = IOLETS.with(|i| i.borrow_mut().remove(&(pointer as usize)));
+        let iolets = iolets.unwrap_or_default();
+        let signal_inlets = iolets.signal_inlets + usize::from(unsafe { (*class).signal_inlet });
+
+        Some(Object {
+            pointer,
+            class,
+            signal_inlets,
+            signal_outlets: iolets.signal_outlets,
+            outlets: iolets.outlets,
+        })
+    }
+
+    /// Send a list to receivers of the symbol, e.g. a control change to
+    /// `#ctlin`.
+    pub fn send_list(&self, symbol: &str, values: &[f32]) {
+        let receivers = BINDINGS.with(|b| b.borrow().get(symbol).cloned().unwrap_or_default());
+        let mut atoms: Vec<t_atom> = values
+            .iter()
+            .map(|value| {
+                let mut atom: t_atom = unsafe { std::mem::zeroed() };
+                atom.a_type = t_atomtype::A_FLOAT;
+                atom.a_w.w_float = *value;
+                atom
+            })
+            .collect();
+        for receiver in receivers {
+            unsafe {
+                let class = *(receiver as *mut t_pd) as *mut MockClass;
+                let method = (*class).list.expect("receiver has no list method");
+                std::mem::transmute::<Method, GimmeMethod>(method)(
+                    receiver as *mut c_void,
+                    gensym(CString::new("list").unwrap().as_ptr()),
+                    atoms.len() as c_int,
+                    atoms.as_mut_ptr(),
+                );
+            }
+        }
+    }
+
+    /// Send a message given as text to the receiver the symbol points to,
+    /// the way lines following an object in a patch file are sent to `#A`.
+    pub fn send_message(&self, symbol: &str, text: &str) {
+        let receiver = self
+            .receiver(symbol)
+            .expect("nothing is bound to the symbol");
+        let (selector, mut arguments) = parse_message(text);
+        unsafe {
+            let class = *receiver as *mut MockClass;
+            let (method, kind) = (&(*class).methods)[&selector];
+            assert_eq!(
+                kind,
+                t_atomtype::A_GIMME,
+                "method {} has different type",
+                selector
+            );
+            std::mem::transmute::<Method, GimmeMethod>(method)(
+                receiver as *mut c_void,
+                gensym(CString::new(selector).unwrap().as_ptr()),
+                arguments.len() as c_int,
+                arguments.as_mut_ptr(),
+            );
+        }
+    }
+
+    /// The receiver the symbol points to, if any.
+    pub fn receiver(&self, symbol: &str) -> Option<*mut t_pd> {
+        let symbol = unsafe { gensym(CString::new(symbol).unwrap().as_ptr()) };
+        let receiver = unsafe { (*symbol).s_thing };
+        (!receiver.is_null()).then_some(receiver)
+    }
+
+    /// Create an array of the given size filled with zeros, like `[array
+    /// define]` would.
+    pub fn create_array(&self, name: &str, size: usize) {
+        let array = MockArray {
+            words: vec![t_word { w_float: 0.0 }; size],
+            redraws: 0,
+        };
+        ARRAYS.with(|a| a.borrow_mut().insert(name.to_owned(), Box::new(array)));
+    }
+
+    pub fn array(&self, name: &str) -> Vec<f32> {
+        ARRAYS.with(|a| {
+            a.borrow()[name]
+                .words
+                .iter()
+                .map(|w| unsafe { w.w_float })
+                .collect()
+        })
+    }
+
+    /// Number of times the array was redrawn since it was created.
+    pub fn array_redraws(&self, name: &str) -> usize {
+        ARRAYS.with(|a| a.borrow()[name].redraws)
+    }
+
+    pub fn log(&self) -> Vec<String> {
+        LOG.with(|l| l.borrow().clone())
+    }
+
+    pub fn clear_log(&self) {
+        LOG.with(|l| l.borrow_mut().clear());
+    }
+}
+
+pub struct Object {
+    pointer: *mut t_object,
+    class: *mut MockClass,
+    signal_inlets: usize,
+    signal_outlets: usize,
+    outlets: Vec<*mut MockOutlet>,
+}
+
+impl Object {
+    pub fn as_pd(&self) -> *mut t_pd {
+        self.pointer as *mut t_pd
+    }
+
+    pub fn signal_inlets(&self) -> usize {
+        self.signal_inlets
+    }
+
+    pub fn signal_outlets(&self) -> usize {
+        self.signal_outlets
+    }
+
+    /// Like Pure Data, a float is passed to a method accepting any arguments
+    /// if there is no float method of the name.
+    pub fn send_float(&mut self, selector: &str, value: f32) {
+        if self.has_method(selector, t_atomtype::A_GIMME) {
+            self.send_message(&format!("{} {}", selector, value));
+            return;
+        }
+        let method = self.method(selector, t_atomtype::A_FLOAT);
+        unsafe {
+            std::mem::transmute::<Method, FloatMethod>(method)(self.pointer as *mut c_void, value)
+        };
+    }
+
+    pub fn send(&mut self, selector: &str) {
+        let method = self.method(selector, t_atomtype::A_NULL);
+        unsafe { std::mem::transmute::<Method, BangMethod>(method)(self.pointer as *mut c_void) };
+    }
+
+    /// Send a message given as text, e.g. `pool 64`, to a method accepting
+    /// any arguments.
+    pub fn send_message(&mut self, text: &str) {
+        let (selector, mut arguments) = parse_message(text);
+        let method = self.method(&selector, t_atomtype::A_GIMME);
+        unsafe {
+            std::mem::transmute::<Method, GimmeMethod>(method)(
+                self.pointer as *mut c_void,
+                gensym(CString::new(selector).unwrap().as_ptr()),
+                arguments.len() as c_int,
+                arguments.as_mut_ptr(),
+            )
+        };
+    }
+
+    /// Text the object would be saved as in a patch.
+    pub fn save(&self) -> String {
+        let save = unsafe { (*self.class).save }.expect("class has no save method");
+        let mut binbuf = MockBinbuf::default();
+        unsafe {
+            save(
+                self.pointer as *mut t_gobj,
+                &mut binbuf as *mut MockBinbuf as *mut t_binbuf,
+            )
+        };
+        binbuf.words.join(" ").replace(" ;", ";").replace(" ,", ",")
+    }
+
+    pub fn send_bang(&mut self) {
+        let method = unsafe { (*self.class).bang }.expect("class has no bang method");
+        unsafe { std::mem::transmute::<Method, BangMethod>(method)(self.pointer as *mut c_void) };
+    }
+
+    /// Messages sent so far through the given control outlet, counting only
+    /// outlets that are not signal ones.
+    pub fn messages(&self, outlet: usize) -> Vec<Message> {
+        let outlet = self
+            .outlets
+            .iter()
+            .copied()
+            .filter(|o| unsafe { !(**o).signal })
+            .nth(outlet)
+            .expect("outlet does not exist");
+        unsafe { (*outlet).messages.clone() }
+    }
+
+    /// Compile the DSP graph of this object alone and run it for a single
+    /// block. Missing inputs are filled with silence.
+    pub fn process(&mut self, inputs: &[&[f32]], number_of_frames: usize) -> Vec<Vec<f32>> {
+        let method = self.method("dsp", t_atomtype::A_CANT);
+
+        let mut buffers: Vec<Vec<f32>> = (0..self.signal_inlets)
+            .map(|i| {
+                let mut buffer = vec![0.0; number_of_frames];
+                if let Some(input) = inputs.get(i) {
+                    buffer[..input.len()].copy_from_slice(input);
+                }
+                buffer
+            })
+            .chain((0..self.signal_outlets).map(|_| vec![0.0; number_of_frames]))
+            .collect();
+
+        let sample_rate = SAMPLE_RATE.with(|s| s.get());
+        let mut signals: Vec<t_signal> = buffers
+            .iter_mut()
+            .map(|buffer| {
+                let mut signal: t_signal = unsafe { std::mem::zeroed() };
+                signal.s_n = number_of_frames as c_int;
+                signal.s_vec = buffer.as_mut_ptr();
+                signal.s_sr = sample_rate;
+                signal
+            })
+            .collect();
+        let mut pointers: Vec<*mut t_signal> =
+            signals.iter_mut().map(|s| s as *mut t_signal).collect();
+
+        DSP_CHAIN.with(|c| c.borrow_mut().clear());
+        unsafe {
+            std::mem::transmute::<Method, DspMethod>(method)(
+                self.pointer as *mut c_void,
+                pointers.as_mut_ptr(),
+            );
+        }
+
+        let chain = DSP_CHAIN.with(|c| c.borrow_mut().split_off(0));
+        for mut routine in chain {
+            let perform = unsafe { std::mem::transmute::<t_int, PerformRoutine>(routine[0]) };
+            unsafe { perform(routine.as_mut_ptr()) };
+        }
+        advance_time(number_of_frames as f64 * 1000.0 / f64::from(sample_rate));
+
+        buffers.split_off(self.signal_inlets)
+    }
+
+    fn has_method(&self, selector: &str, kind: t_atomtype::Type) -> bool {
+        unsafe { &(*self.class).methods }
+            .get(selector)
+            .is_some_and(|(_, registered_kind)| *registered_kind == kind)
+    }
+
+    fn method(&self, selector: &str, kind: t_atomtype::Type) -> Method {
+        let (method, registered_kind) = *unsafe { &(*self.class).methods }
+            .get(selector)
+            .unwrap_or_else(|| panic!("method {} is not registered", selector));
+        assert_eq!(
+            registered_kind, kind,
+            "method {} has different type",
+            selector
+        );
+        method
+    }
+}
+
+impl Drop for Object {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(free) = (*self.class).free {
+                std::mem::transmute::<Method, unsafe extern "C" fn(*mut t_object)>(free)(
+                    self.pointer,
+                );
+            }
+            drop(Box::from_raw((*self.pointer).te_binbuf as *mut MockBinbuf));
+            freebytes(self.pointer as *mut c_void, (*self.class).size);
+            for outlet in &self.outlets {
+                drop(Box::from_raw(*outlet));
+            }
+        }
+    }
+}
+
+fn symbol_name(symbol: *mut t_symbol) -> String {
+    unsafe { CStr::from_ptr((*symbol).s_name) }
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+/// Move the logical time forward and fire clocks that got due, in order of
+/// their deadlines.
+fn advance_time(milliseconds: f64) {
+    let now = TIME.with(|t| {
+        t.set(t.get() + milliseconds);
+        t.get()
+    });
+    loop {
+        let due = CLOCKS.with(|c| {
+            c.borrow()
+                .iter()
+                .copied()
+                .filter(|clock| unsafe { (**clock).deadline }.is_some_and(|d| d <= now))
+                .min_by(|a, b| unsafe { (**a).deadline.partial_cmp(&(**b).deadline).unwrap() })
+        });
+        let Some(clock) = due else {
+            break;
+        };
+        unsafe {
+            (*clock).deadline = None;
+            ((*clock).method)((*clock).owner);
+        }
+    }
+}
+
+/// Split text into its first word and atoms of the remaining ones.
+fn parse_message(text: &str) -> (String, Vec<t_atom>) {
+    let mut words = text.split_whitespace();
+    let selector = words.next().expect("message must not be empty").to_owned();
+    let atoms = words
+        .map(|word| {
+            let mut atom: t_atom = unsafe { std::mem::zeroed() };
+            match word.parse::<f32>() {
+                Ok(value) => {
+                    atom.a_type = t_atomtype::A_FLOAT;
+                    atom.a_w.w_float = value;
+                }
+                Err(_) => {
+                    atom.a_type = t_atomtype::A_SYMBOL;
+                    atom.a_w.w_symbol = unsafe { gensym(CString::new(word).unwrap().as_ptr()) };
+                }
+            }
+            atom
+        })
+        .collect();
+    (selector, atoms)
+}
+
+fn with_iolets(owner: *mut t_object, f: impl FnOnce(&mut Iolets)) {
+    IOLETS.with(|i| f(i.borrow_mut().entry(owner as usize).or_default()));
+}
+
+#[no_mangle]
+pub static mut s_signal: t_symbol = t_symbol {
+    s_name: c"signal".as_ptr(),
+    s_thing: std::ptr::null_mut(),
+    s_next: std::ptr::null_mut(),
+};
+
+#[no_mangle]
+pub unsafe extern "C" fn gensym(name: *const c_char) -> *mut t_symbol {
+    let name = CStr::from_ptr(name).to_str().unwrap().to_owned();
+    if name == "signal" {
+        return std::ptr::addr_of_mut!(s_signal);
+    }
+    let new_symbol = |name: String| {
+        let symbol = Box::new(t_symbol {
+            s_name: CString::new(name).unwrap().into_raw(),
+            s_thing: std::ptr::null_mut(),
+            s_next: std::ptr::null_mut(),
+        });
+        Box::into_raw(symbol) as usize
+    };
+    let symbol = if name.starts_with('#') {
+        LOCAL_SYMBOLS.with(|s| {
+            *s.borrow_mut()
+                .entry(name.clone())
+                .or_insert_with(|| new_symbol(name))
+        })
+    } else {
+        let mut symbols = SYMBOLS.lock().unwrap();
+        *symbols
+            .entry(name.clone())
+            .or_insert_with(|| new_symbol(name))
+    };
+    symbol as *mut t_symbol
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_new(
+    name: *mut t_symbol,
+    new: Option<NewMethod>,
+    free: Option<Method>,
+    size: usize,
+    _flags: c_int,
+    arg1: t_atomtype::Type,
+) -> *mut t_class {
+    let class = Box::new(MockClass {
+        name: symbol_name(name),
+        new,
+        new_arguments: arg1,
+        free,
+        size,
+        signal_inlet: false,
+        methods: HashMap::new(),
+        bang: None,
+        list: None,
+        save: Some(text_save),
+    });
+    let name = class.name.clone();
+    let class = Box::into_raw(class);
+    CLASSES.lock().unwrap().insert(name, class as usize);
+    class as *mut t_class
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_addmethod(
+    class: *mut t_class,
+    method: Option<Method>,
+    selector: *mut t_symbol,
+    arg1: t_atomtype::Type,
+) {
+    let class = class as *mut MockClass;
+    (*class)
+        .methods
+        .insert(symbol_name(selector), (method.unwrap(), arg1));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_addbang(class: *mut t_class, method: Option<Method>) {
+    let class = class as *mut MockClass;
+    (*class).bang = method;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_addlist(class: *mut t_class, method: Option<Method>) {
+    let class = class as *mut MockClass;
+    (*class).list = method;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_getsavefn(class: *const t_class) -> Option<SaveMethod> {
+    (*(class as *const MockClass)).save
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_setsavefn(class: *mut t_class, method: Option<SaveMethod>) {
+    let class = class as *mut MockClass;
+    (*class).save = method;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_domainsignalin(class: *mut t_class, _onset: c_int) {
+    let class = class as *mut MockClass;
+    (*class).signal_inlet = true;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pd_new(class: *mut t_class) -> *mut t_pd {
+    let size = (*(class as *mut MockClass)).size;
+    let object = getbytes(size) as *mut t_object;
+    (*object).te_g.g_pd = class as _;
+    with_iolets(object, |_| {});
+    object as *mut t_pd
+}
+
+/// Like in Pure Data, the symbol points to its receiver. Clearing it unbinds
+/// all receivers at once.
+#[no_mangle]
+pub unsafe extern "C" fn pd_bind(object: *mut t_pd, symbol: *mut t_symbol) {
+    BINDINGS.with(|b| {
+        let mut bindings = b.borrow_mut();
+        let receivers = bindings.entry(symbol_name(symbol)).or_default();
+        if (*symbol).s_thing.is_null() {
+            receivers.clear();
+            (*symbol).s_thing = object;
+        }
+        receivers.push(object as usize)
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pd_unbind(object: *mut t_pd, symbol: *mut t_symbol) {
+    BINDINGS.with(|b| {
+        let mut bindings = b.borrow_mut();
+        let receivers = bindings.entry(symbol_name(symbol)).or_default();
+        let position = receivers
+            .iter()
+            .position(|r| *r == object as usize)
+            .expect("object is not bound to the symbol");
+        receivers.remove(position);
+        (*symbol).s_thing = receivers
+            .first()
+            .map_or(std::ptr::null_mut(), |r| *r as *mut t_pd);
+    });
+}
+
+/// What Pure Data saves for object boxes of classes without a save function.
+unsafe extern "C" fn text_save(object: *mut t_gobj, binbuf: *mut t_binbuf) {
+    let object = object as *mut t_object;
+    let words = &(*((*object).te_binbuf as *const MockBinbuf)).words;
+    let binbuf = &mut *(binbuf as *mut MockBinbuf);
+    binbuf
+        .words
+        .extend(["#X", "obj", "0", "0"].map(str::to_owned));
+    binbuf.words.extend(words.iter().cloned());
+    binbuf.words.push(";".to_owned());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn binbuf_add(binbuf: *mut t_binbuf, argc: c_int, argv: *const t_atom) {
+    let binbuf = &mut *(binbuf as *mut MockBinbuf);
+    for atom in std::slice::from_raw_parts(argv, argc as usize) {
+        binbuf.words.push(match atom.a_type {
+            t_atomtype::A_FLOAT => atom.a_w.w_float.to_string(),
+            t_atomtype::A_SYMBOL => symbol_name(atom.a_w.w_symbol),
+            t_atomtype::A_SEMI => ";".to_owned(),
+            t_atomtype::A_COMMA => ",".to_owned(),
+            _ => panic!("unsupported atom type"),
+        });
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn binbuf_addsemi(binbuf: *mut t_binbuf) {
+    (*(binbuf as *mut MockBinbuf)).words.push(";".to_owned());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn outlet_new(owner: *mut t_object, symbol: *mut t_symbol) -> *mut t_outlet {
+    let signal = symbol == std::ptr::addr_of_mut!(s_signal);
+    let outlet = Box::into_raw(Box::new(MockOutlet {
+        signal,
+        messages: Vec::new(),
+    }));
+    with_iolets(owner, |iolets| {
+        if signal {
+            iolets.signal_outlets += 1;
+        }
+        iolets.outlets.push(outlet);
+    });
+    outlet as *mut t_outlet
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn outlet_anything(
+    outlet: *mut t_outlet,
+    selector: *mut t_symbol,
+    argc: c_int,
+    argv: *mut t_atom,
+) {
+    let mut message = symbol_name(selector);
+    for atom in std::slice::from_raw_parts(argv, argc as usize) {
+        message.push(' ');
+        match atom.a_type {
+            t_atomtype::A_FLOAT => message.push_str(&atom.a_w.w_float.to_string()),
+            t_atomtype::A_SYMBOL => message.push_str(&symbol_name(atom.a_w.w_symbol)),
+            _ => panic!("unsupported atom type"),
+        }
+    }
+    (*(outlet as *mut MockOutlet))
+        .messages
+        .push(Message::Anything(message));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn inlet_new(
+    owner: *mut t_object,
+    _destination: *mut t_pd,
+    from: *mut t_symbol,
+    _to: *mut t_symbol,
+) -> *mut t_inlet {
+    if from == std::ptr::addr_of_mut!(s_signal) {
+        with_iolets(owner, |iolets| iolets.signal_inlets += 1);
+    }
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signalinlet_new(owner: *mut t_object, _value: f32) -> *mut t_inlet {
+    with_iolets(owner, |iolets| iolets.signal_inlets += 1);
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn clock_new(owner: *mut c_void, method: Option<Method>) -> *mut c_void {
+    let clock = Box::into_raw(Box::new(MockClock {
+        owner,
+        method: std::mem::transmute::<Method, BangMethod>(method.expect("clock needs a method")),
+        deadline: None,
+    }));
+    CLOCKS.with(|c| c.borrow_mut().push(clock));
+    clock as *mut c_void
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn clock_delay(clock: *mut c_void, milliseconds: f64) {
+    let clock = clock as *mut MockClock;
+    (*clock).deadline = Some(TIME.with(|t| t.get()) + milliseconds);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn clock_unset(clock: *mut c_void) {
+    (*(clock as *mut MockClock)).deadline = None;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn clock_free(clock: *mut c_void) {
+    CLOCKS.with(|c| {
+        c.borrow_mut()
+            .retain(|other| *other as *mut c_void != clock)
+    });
+    drop(Box::from_raw(clock as *mut MockClock));
+}
+
+#[no_mangle]
+pub static mut garray_class: *mut t_class = std::ptr::null_mut();
+
+/// Only arrays are looked up by the library, so the class is not checked.
+#[no_mangle]
+pub unsafe extern "C" fn pd_findbyclass(
+    symbol: *mut t_symbol,
+    _class: *const t_class,
+) -> *mut t_pd {
+    let name = symbol_name(symbol);
+    ARRAYS.with(|a| {
+        a.borrow_mut()
+            .get_mut(&name)
+            .map_or(std::ptr::null_mut(), |array| {
+                &mut **array as *mut MockArray as *mut t_pd
+            })
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn garray_getfloatwords(
+    array: *mut t_garray,
+    size: *mut c_int,
+    words: *mut *mut t_word,
+) -> c_int {
+    let array = array as *mut MockArray;
+    *size = (*array).words.len() as c_int;
+    *words = (*array).words.as_mut_ptr();
+    1
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn garray_redraw(array: *mut t_garray) {
+    (*(array as *mut MockArray)).redraws += 1;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dsp_addv(routine: Option<PerformRoutine>, n: c_int, vector: *mut t_int) {
+    let mut entry = vec![routine.unwrap() as usize as t_int];
+    entry.extend_from_slice(std::slice::from_raw_parts(vector, n as usize));
+    DSP_CHAIN.with(|c| c.borrow_mut().push(entry));
+}
+
+/// Objects are created as if their patch was saved in the temporary
+/// directory.
+#[no_mangle]
+pub unsafe extern "C" fn canvas_getcurrentdir() -> *mut t_symbol {
+    let directory = std::env::temp_dir();
+    gensym(CString::new(directory.to_str().unwrap()).unwrap().as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn sys_getsr() -> f32 {
+    SAMPLE_RATE.with(|s| s.get())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getbytes(size: usize) -> *mut c_void {
+    alloc::alloc_zeroed(Layout::from_size_align(size.max(1), ALIGNMENT).unwrap()) as *mut c_void
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn freebytes(pointer: *mut c_void, size: usize) {
+    alloc::dealloc(
+        pointer as *mut u8,
+        Layout::from_size_align(size.max(1), ALIGNMENT).unwrap(),
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pd_free(object: *mut t_pd) {
+    let class = *object as *mut MockClass;
+    if let Some(free) = (*class).free {
+        std::mem::transmute::<Method, unsafe extern "C" fn(*mut t_pd)>(free)(object);
+    }
+    if let Some(iolets) = IOLETS.with(|i| i.borrow_mut().remove(&(object as usize))) {
+        for outlet in iolets.outlets {
+            drop(Box::from_raw(outlet));
+        }
+    }
+    freebytes(object as *mut c_void, (*class).size);
+}
+
+/// The library always logs through `"%s"`, followed by the message.
+#[no_mangle]
+pub unsafe extern "C" fn pd_error(
+    _object: *const c_void,
+    _format: *const c_char,
+    message: *const c_char,
+) {
+    push_log("error: ", message);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn logpost(
+    _object: *const c_void,
+    level: c_int,
+    _format: *const c_char,
+    message: *const c_char,
+) {
+    let prefix = match level {
+        0 | 1 => "error: ",
+        2 => "",
+        _ => "debug: ",
+    };
+    push_log(prefix, message);
+}
+
+unsafe fn push_log(prefix: &str, message: *const c_char) {
+    let message = CStr::from_ptr(message).to_string_lossy();
+    LOG.with(|l| l.borrow_mut().push(format!("{}{}", prefix, message)));
+}
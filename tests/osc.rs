@@ -0,0 +1,112 @@
+#![cfg(not(feature = "pd64"))]
+
+//! Objects are addressed by their number in the Pure Data instance, so this
+//! test runs in a binary of its own, away from objects of other tests.
+
+mod mock;
+
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+use mock::{Host, Message};
+
+const BLOCK: usize = 64;
+
+fn padded(string: &str) -> Vec<u8> {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.resize((string.len() + 1).next_multiple_of(4), 0);
+    bytes
+}
+
+fn osc_message(address: &str, value: f32) -> Vec<u8> {
+    let mut packet = padded(address);
+    packet.extend(padded(",f"));
+    packet.extend(value.to_be_bytes());
+    packet
+}
+
+#[test]
+fn it_dispatches_osc_messages_to_addressed_objects() {
+    let host = Host::new(48000.0);
+    let mut automaton = host.create("automaton");
+    let mut first = host.create("kaseta~");
+    let mut second = host.create("kaseta~");
+    let mut achordion = host.create("achordion~");
+    for object in [&mut first, &mut second, &mut achordion] {
+        object.send_float("debug", 1.0);
+    }
+
+    automaton.send_message("osc 0 localhost");
+    assert_eq!(
+        host.log().last().unwrap(),
+        "error: [automaton] osc expects an IP address to listen on"
+    );
+
+    automaton.send_message("osc 0");
+    let port = match automaton.messages(0).last() {
+        Some(Message::Anything(message)) => message
+            .strip_prefix("osc ")
+            .expect("port must be reported")
+            .parse::<u16>()
+            .unwrap(),
+        _ => panic!("port must be reported"),
+    };
+
+    assert_eq!(
+        host.log().last().unwrap(),
+        &format!("[automaton] listening for OSC on 127.0.0.1:{}", port)
+    );
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for (address, value) in [
+        ("/kaseta/2/head/2/feedback", 0.5),
+        ("/achordion/1/detune", 0.25),
+        ("/kaseta/3/tone", 1.0),
+        ("/kaseta/1/tempo", 1.0),
+    ] {
+        socket
+            .send_to(&osc_message(address, value), ("127.0.0.1", port))
+            .unwrap();
+    }
+
+    let expected = [
+        "debug: [kaseta~] head_2_feedback 0.5",
+        "debug: [achordion~] detune 0.25",
+    ];
+    let start = Instant::now();
+    while !expected.iter().all(|e| host.log().iter().any(|l| l == e)) {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "messages were not delivered: {:?}",
+            host.log()
+        );
+        std::thread::sleep(Duration::from_millis(10));
+        first.process(&[], BLOCK);
+        second.process(&[], BLOCK);
+        achordion.process(&[], BLOCK);
+    }
+
+    let start = Instant::now();
+    loop {
+        automaton.send_message("osc");
+        if let Some(Message::Anything(m)) = automaton.messages(0).last() {
+            if *m == format!("osc {} 2 2", port) {
+                break;
+            }
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let traces: Vec<_> = host
+        .log()
+        .into_iter()
+        .filter(|l| l.starts_with("debug") && !l.ends_with("initializing"))
+        .collect();
+    assert_eq!(traces.len(), 2);
+
+    automaton.send_message("osc off");
+    assert!(host
+        .log()
+        .contains(&"[automaton] stopped listening for OSC".to_string()));
+}
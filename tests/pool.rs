@@ -0,0 +1,41 @@
+#![cfg(not(feature = "pd64"))]
+
+//! The pool is shared by the whole process, so its life cycle is exercised
+//! by a single test.
+
+mod mock;
+
+use mock::{Host, Message};
+
+#[test]
+fn it_sizes_pool_before_first_allocation_and_reports_usage() {
+    let host = Host::new(8000.0);
+    let mut automaton = host.create("automaton 8");
+    assert!(host.log().iter().any(|l| l.contains("pool set to 8 MB")));
+
+    automaton.send_message("pool");
+    assert_eq!(
+        automaton.messages(0),
+        vec![Message::Anything("pool 0 0 8".into())]
+    );
+
+    let _kaseta = host.create("kaseta~");
+    automaton.send_message("pool");
+    let Message::Anything(report) = automaton.messages(0).pop().unwrap() else {
+        panic!("pool must be reported with a message");
+    };
+    let values: Vec<f32> = report
+        .split(' ')
+        .skip(1)
+        .map(|v| v.parse().unwrap())
+        .collect();
+    assert!(values[0] > 0.0);
+    assert_eq!(values[0], values[1]);
+    assert_eq!(values[2], 8.0);
+
+    automaton.send_message("pool 4");
+    assert!(host
+        .log()
+        .iter()
+        .any(|l| l.contains("only resized before the first tape")));
+}
@@ -0,0 +1,29 @@
+#![cfg(not(feature = "pd64"))]
+
+//! The pool is shared by the whole process, so running out of it needs a
+//! process of its own.
+
+mod mock;
+
+use mock::{Host, Message};
+
+#[test]
+fn it_refuses_to_create_tape_that_does_not_fit_into_pool() {
+    let host = Host::new(8000.0);
+    let mut automaton = host.create("automaton 0.25");
+
+    assert!(host.try_create("kaseta~").is_none());
+    assert!(host
+        .log()
+        .iter()
+        .any(|l| l
+            .starts_with("error: [kaseta~] 5.5 MB of tape memory needed, only 0.2 MB available")));
+
+    // Nothing is left taken by the refused object, others are still created.
+    let _achordion = host.create("achordion~");
+    automaton.send_message("pool");
+    assert_eq!(
+        automaton.messages(0),
+        vec![Message::Anything("pool 0 0 0.25".into())]
+    );
+}